@@ -1,6 +1,76 @@
 #![no_std]
 
-use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Env, Symbol, Vec};
+use soroban_sdk::{
+    contract, contracterror, contractimpl, contracttype, token, xdr::ToXdr, Address, Bytes, BytesN,
+    Env, IntoVal, String, Symbol, Topics, Val, Vec,
+};
+
+/// Errors returned by vault entry points. The generated client's `try_*` methods
+/// surface these as `Err(Ok(VaultError::..))`.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum VaultError {
+    /// `init` was called on a vault that is already initialized.
+    AlreadyInitialized = 1,
+    /// Caller does not hold the role (admin or owner) the call requires.
+    Unauthorized = 2,
+    /// Internal balance is too low for the deduct or withdrawal.
+    InsufficientBalance = 3,
+    /// The vault has not been initialized.
+    NotInitialized = 4,
+    /// Amount must be strictly positive.
+    AmountNotPositive = 5,
+    /// The vault holds less USDC than the requested transfer.
+    InsufficientUsdcBalance = 6,
+    /// Deposit is below the minimum deposit currently in force.
+    BelowMinDeposit = 7,
+    /// `batch_deduct` was called with no items.
+    EmptyBatch = 8,
+    /// A configuration value is out of range.
+    InvalidConfig = 9,
+    /// Public `balance()` is disabled for this vault; use `private_balance`.
+    BalancePrivate = 10,
+    /// `accrue` would push the unpaid tab above the admin-set tab limit.
+    TabLimitExceeded = 11,
+    /// Deduct is above the cosign threshold; use `deduct_cosigned`.
+    CosignRequired = 12,
+    /// Deducts are rejected during the admin-set blackout window.
+    InBlackout = 13,
+    /// The token reported a successful transfer but the vault's balance did not move
+    /// by the transferred amount.
+    TokenTransferMismatch = 14,
+    /// No pending deposit with this id belongs to the caller.
+    PendingDepositNotFound = 15,
+    /// Deduct reason is not in the reason registry.
+    UnknownReason = 16,
+    /// The same request id was charged less than the minimum interval ago.
+    RequestIdTooSoon = 17,
+    /// Deposit amount is not one of the configured deposit tiers.
+    NotAValidTier = 18,
+    /// The owner's auth policy contract rejected the deduct.
+    PolicyRejected = 19,
+    /// A partial refund is larger than what remains of the deduct it refunds.
+    RefundExceedsDeduct = 20,
+    /// The deduct's request id is on the admin's blocklist.
+    RequestIdBlocked = 21,
+    /// A deduct arrived after the deadline it was submitted with.
+    DeadlinePassed = 22,
+    /// Withdrawal amount is below the configured minimum withdrawal.
+    BelowMinWithdraw = 23,
+    /// Deduct amount is above the per-deduct cap set by `set_max_deduct_bps`.
+    AboveMaxDeduct = 24,
+    /// The per-ledger deduct limit has been reached in the current ledger.
+    TooManyDeducts = 25,
+    /// A sequenced deduct's seq is not the one after the namespace's last seq.
+    OutOfOrderSeq = 26,
+    /// The deduct would take this calendar month's deducts past the monthly budget.
+    MonthlyBudgetExceeded = 27,
+    /// Deducts in the current ratio window would exceed the allowed share of deposits.
+    DeductDepositRatioExceeded = 28,
+    /// Refund would exceed the revenue still retained by the vault.
+    RefundExceedsRevenue = 29,
+}
 
 /// Single item for batch deduct: amount and optional request id for idempotency/tracking.
 #[contracttype]
@@ -11,17 +81,166 @@ pub struct DeductItem {
 }
 
 #[contracttype]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct VaultMeta {
     pub owner: Address,
     pub balance: i128,
-    /// Minimum amount required per deposit; deposits below this panic.
+    /// Minimum amount required per deposit; deposits below this are rejected.
     pub min_deposit: i128,
 }
 
 const META_KEY: &str = "meta";
 const USDC_KEY: &str = "usdc";
 const ADMIN_KEY: &str = "admin";
+const DEDUCT_TOLERANCE_KEY: &str = "deduct_tol";
+const DEDUCT_BASE_FEE_KEY: &str = "deduct_base";
+const MIN_WITHDRAW_KEY: &str = "min_withdraw";
+const MAX_DEDUCT_BPS_KEY: &str = "max_deduct_bps";
+const MAX_LEDGER_DEDUCTS_KEY: &str = "max_ldg_deducts";
+const LEDGER_DEDUCTS_KEY: &str = "ldg_deducts";
+const RECEIPT_NONCE_KEY: &str = "rcpt_nonce";
+const SEQ_KEY: &str = "seq";
+const OP_LOG_KEY: &str = "op_log";
+const OP_LOG_SIZE_KEY: &str = "op_log_sz";
+const OP_LOG_POS_KEY: &str = "op_log_pos";
+/// Largest op log size `set_op_log_size` accepts.
+const MAX_OP_LOG_SIZE: u32 = 100;
+const MONTHLY_BUDGET_KEY: &str = "month_budget";
+const MONTHLY_USED_KEY: &str = "month_used";
+const RATIO_GUARD_KEY: &str = "ratio_guard";
+const RATIO_WINDOW_KEY: &str = "ratio_window";
+const REVENUE_KEY: &str = "revenue";
+const REQUEST_ID_KEY: &str = "req_id";
+const ACCRUAL_ID_KEY: &str = "accrue_id";
+const REQUEST_AMOUNT_KEY: &str = "req_amt";
+const BLOCKED_REQUEST_ID_KEY: &str = "req_blocked";
+const BLOCKLIST_DIGEST_KEY: &str = "blocklist_dig";
+const PENDING_MIN_DEPOSIT_KEY: &str = "pend_min_dep";
+const BALANCE_PRIVATE_KEY: &str = "bal_private";
+const TAB_KEY: &str = "tab";
+const TAB_LIMIT_KEY: &str = "tab_limit";
+const EVENTS_ENABLED_KEY: &str = "events_on";
+const DEDUCT_COSIGN_KEY: &str = "cosign";
+const DEPOSIT_BONUS_KEY: &str = "dep_bonus";
+const BONUS_PAID_KEY: &str = "bonus_paid";
+const DEDUCT_BLACKOUT_KEY: &str = "blackout";
+const PENDING_DEPOSIT_KEY: &str = "pend_dep";
+const PENDING_DEPOSIT_SEQ_KEY: &str = "pend_dep_seq";
+const PENDING_DEPOSIT_TOTAL_KEY: &str = "pend_dep_tot";
+const REASONS_KEY: &str = "reasons";
+const LOW_BALANCE_THRESHOLD_KEY: &str = "low_bal";
+const REQUEST_ID_INTERVAL_KEY: &str = "req_id_ivl";
+const DEDUCT_RESTRICTED_KEY: &str = "deduct_restr";
+const DEPOSIT_TIERS_KEY: &str = "dep_tiers";
+const CONFIG_HISTORY_KEY: &str = "cfg_hist";
+/// Number of config snapshots kept by `config_history`.
+const CONFIG_HISTORY_LEN: u32 = 20;
+const LOW_BALANCE_SIGNALED_KEY: &str = "low_bal_sent";
+const REASON_KEY: &str = "reason";
+const DEDUCT_LOG_KEY: &str = "deduct_log";
+const DEDUCT_LOG_SIZE_KEY: &str = "deduct_log_sz";
+const DEDUCT_LOG_POS_KEY: &str = "deduct_log_pos";
+const AUTH_POLICY_KEY: &str = "auth_policy";
+/// Number of deducts kept by `recent_deducts` until the admin changes it.
+const DEFAULT_DEDUCT_LOG_SIZE: u32 = 20;
+/// Largest deduct log size `set_deduct_log_size` accepts.
+const MAX_DEDUCT_LOG_SIZE: u32 = 100;
+
+/// Per-item result of `simulate_batch_deduct`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemOutcome {
+    pub request_id: Option<Symbol>,
+    /// Simulated balance after this item (unchanged if the item fails).
+    pub balance_after: i128,
+    /// `VaultError` code `batch_deduct` would reject this item with, or `None` if it passes.
+    pub error: Option<u32>,
+}
+
+/// Whether a withdrawal would succeed, as returned by `can_withdraw`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct WithdrawEligibility {
+    pub allowed: bool,
+    /// `VaultError` code the withdrawal would fail with, or `None` if it is allowed.
+    pub error: Option<u32>,
+}
+
+/// Topics and data a `deduct` call would emit, as returned by `preview_deduct_event`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeductEventPreview {
+    pub caller: Address,
+    pub request_id: Option<Symbol>,
+    pub reason: Option<Symbol>,
+    /// Amount actually charged (less than requested only within the tolerance).
+    pub amount: i128,
+    pub new_balance: i128,
+    /// Uncharged remainder; non-zero only when the deduct tolerance applies.
+    pub shortfall: i128,
+    /// Fixed part of the charge, as in the "base_fee" event (0 when no base fee is set).
+    pub base_fee: i128,
+    /// Requested part of the charge, as in the "base_fee" event.
+    pub variable: i128,
+}
+
+/// Token assets held by the vault against the credit it owes, as returned by `solvency`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solvency {
+    /// USDC held by the vault contract.
+    pub assets: i128,
+    /// Credit owed to the owner plus pending deposits (`required_reserve()`).
+    pub liabilities: i128,
+    /// `assets - liabilities`; negative when the vault is under-collateralized.
+    pub surplus: i128,
+}
+
+/// One entry of the bounded on-chain deduct log, as returned by `recent_deducts`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeductRecord {
+    pub caller: Address,
+    /// Amount actually charged.
+    pub amount: i128,
+    pub balance_after: i128,
+    pub request_id: Option<Symbol>,
+    /// Ledger timestamp of the deduct.
+    pub timestamp: u64,
+}
+
+/// USDC received by `initiate_deposit` and held until confirmed or cancelled.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingDeposit {
+    pub from: Address,
+    pub amount: i128,
+}
+
+/// Balance after everything in flight settles, as returned by `net_position`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetPosition {
+    /// Credited balance (`meta.balance`).
+    pub settled: i128,
+    /// USDC held for pending two-phase deposits.
+    pub pending_in: i128,
+    /// Queued outgoing withdrawals; always 0 as withdrawals settle immediately.
+    pub pending_out: i128,
+    /// Funds on hold; always 0 as the vault has no holds.
+    pub holds: i128,
+    /// `settled + pending_in - pending_out - holds`.
+    pub projected: i128,
+}
+
+/// Capacity in which an address is recognised by the vault, highest priority first.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallerRole {
+    Owner,
+    Admin,
+    None,
+}
 
 #[contracttype]
 #[derive(Clone, Debug, PartialEq)]
@@ -36,6 +255,9 @@ pub struct CalloraVault;
 #[contractimpl]
 impl CalloraVault {
     /// Initialize vault for an owner with optional initial balance and minimum deposit.
+    /// `balance_private = Some(true)` hides the balance from the public `balance()` read.
+    /// With `enforce_min_on_init = Some(true)`, a non-zero initial balance below
+    /// `min_deposit` fails with `BelowMinDeposit`, so no sub-minimum credit ever exists.
     /// Emits an "init" event with the owner address and initial balance.
     pub fn init(
         env: Env,
@@ -43,21 +265,23 @@ impl CalloraVault {
         usdc_token: Address,
         initial_balance: Option<i128>,
         min_deposit: Option<i128>,
-    ) -> VaultMeta {
+        balance_private: Option<bool>,
+        enforce_min_on_init: Option<bool>,
+    ) -> Result<VaultMeta, VaultError> {
         owner.require_auth();
         if env.storage().instance().has(&Symbol::new(&env, META_KEY)) {
-            panic!("vault already initialized");
+            return Err(VaultError::AlreadyInitialized);
         }
         let balance = initial_balance.unwrap_or(0);
         let min_deposit_val = min_deposit.unwrap_or(0);
+        if enforce_min_on_init.unwrap_or(false) && balance > 0 && balance < min_deposit_val {
+            return Err(VaultError::BelowMinDeposit);
+        }
         let meta = VaultMeta {
             owner: owner.clone(),
             balance,
             min_deposit: min_deposit_val,
         };
-        env.storage()
-            .instance()
-            .set(&Symbol::new(&env, "meta"), &meta);
         env.storage()
             .instance()
             .set(&Symbol::new(&env, META_KEY), &meta);
@@ -67,32 +291,116 @@ impl CalloraVault {
         env.storage()
             .instance()
             .set(&Symbol::new(&env, ADMIN_KEY), &owner);
+        env.storage().instance().set(
+            &Symbol::new(&env, BALANCE_PRIVATE_KEY),
+            &balance_private.unwrap_or(false),
+        );
 
         // Emit event: topics = (init, owner), data = balance
-        env.events()
-            .publish((Symbol::new(&env, "init"), owner), balance);
+        Self::publish(&env, (Symbol::new(&env, "init"), owner), balance);
+
+        Ok(meta)
+    }
 
-        meta
+    /// Marker for vault discovery: always true on a Callora vault, initialized or not.
+    /// Factories try-call this and only trust addresses that answer `true`.
+    pub fn is_vault(_env: Env) -> bool {
+        true
+    }
+
+    /// Return the vault owner.
+    pub fn get_owner(env: Env) -> Result<Address, VaultError> {
+        Ok(Self::meta(&env)?.owner)
     }
 
     /// Return the current admin address.
-    pub fn get_admin(env: Env) -> Address {
+    pub fn get_admin(env: Env) -> Result<Address, VaultError> {
+        Self::require_initialized(&env)?;
         env.storage()
             .instance()
             .get(&Symbol::new(&env, ADMIN_KEY))
-            .unwrap_or_else(|| panic!("vault not initialized"))
+            .ok_or(VaultError::NotInitialized)
+    }
+
+    /// Return the role `addr` holds in this vault. The owner is reported as
+    /// `Owner` even when it is also the admin.
+    pub fn caller_role(env: Env, addr: Address) -> Result<CallerRole, VaultError> {
+        Self::require_initialized(&env)?;
+        let role = if addr == Self::meta(&env)?.owner {
+            CallerRole::Owner
+        } else if addr == Self::get_admin(env)? {
+            CallerRole::Admin
+        } else {
+            CallerRole::None
+        };
+        Ok(role)
+    }
+
+    /// Return whether `addr` is the vault owner.
+    pub fn owned_by(env: Env, addr: Address) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(addr == Self::meta(&env)?.owner)
+    }
+
+    /// Return whether `addr` is the vault admin.
+    pub fn administered_by(env: Env, addr: Address) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(addr == Self::get_admin(env)?)
     }
 
     /// Replace the current admin. Only the existing admin may call this.
-    pub fn set_admin(env: Env, caller: Address, new_admin: Address) {
+    pub fn set_admin(env: Env, caller: Address, new_admin: Address) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
         caller.require_auth();
-        let current_admin = Self::get_admin(env.clone());
+        let current_admin = Self::get_admin(env.clone())?;
         if caller != current_admin {
-            panic!("unauthorized: caller is not admin");
+            return Err(VaultError::Unauthorized);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, ADMIN_KEY), &new_admin);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Hand the vault over to a new owner and a new admin in one call, so the roles are
+    /// never split between old and new holders. The owner calls this and the current
+    /// admin must also authorize (one signature when they are the same address). Fails with `InvalidConfig` if either new address equals
+    /// the one it replaces. Emits a "handover" event with the new owner and admin.
+    pub fn handover(
+        env: Env,
+        caller: Address,
+        new_owner: Address,
+        new_admin: Address,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        let mut meta = Self::meta(&env)?;
+        if caller != meta.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        let admin = Self::get_admin(env.clone())?;
+        if admin != caller {
+            admin.require_auth();
         }
+        if new_owner == meta.owner || new_admin == admin {
+            return Err(VaultError::InvalidConfig);
+        }
+
+        meta.owner = new_owner.clone();
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, META_KEY), &meta);
         env.storage()
             .instance()
             .set(&Symbol::new(&env, ADMIN_KEY), &new_admin);
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "handover"),),
+            (new_owner, new_admin),
+        );
+        Self::record_config_change(&env)?;
+        Ok(())
     }
 
     /// Distribute accumulated USDC to a single developer address.
@@ -105,189 +413,2488 @@ impl CalloraVault {
     /// * `to`     – Developer wallet to receive the USDC.
     /// * `amount` – Amount in USDC micro-units (must be > 0 and ≤ vault balance).
     ///
-    /// # Panics
-    /// * `"unauthorized: caller is not admin"` – caller is not the admin.
-    /// * `"amount must be positive"`           – amount is zero or negative.
-    /// * `"insufficient USDC balance"`         – vault holds less than amount.
+    /// # Errors
+    /// * `Unauthorized`            – caller is not the admin.
+    /// * `AmountNotPositive`       – amount is zero or negative.
+    /// * `InsufficientUsdcBalance` – vault holds less than amount.
+    ///
+    /// The payout comes out of retained revenue first, so `withdraw_revenue` cannot pay
+    /// the same revenue out again. Paying the vault itself moves no USDC and leaves
+    /// retained revenue as it was.
     ///
     /// # Events
     /// Emits topic `("distribute", to)` with data `amount` on success.
-    pub fn distribute(env: Env, caller: Address, to: Address, amount: i128) {
+    pub fn distribute(
+        env: Env,
+        caller: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+
         // 1. Require on-chain signature from caller.
         caller.require_auth();
 
         // 2. Only the registered admin may distribute.
-        let admin = Self::get_admin(env.clone());
+        let admin = Self::get_admin(env.clone())?;
         if caller != admin {
-            panic!("unauthorized: caller is not admin");
+            return Err(VaultError::Unauthorized);
         }
 
         // 3. Amount must be positive.
         if amount <= 0 {
-            panic!("amount must be positive");
+            return Err(VaultError::AmountNotPositive);
         }
 
         // 4. Load the USDC token address.
-        let usdc_address: Address = env
-            .storage()
-            .instance()
-            .get(&Symbol::new(&env, USDC_KEY))
-            .unwrap_or_else(|| panic!("vault not initialized"));
+        let usdc_address = Self::usdc_address(&env)?;
 
         let usdc = token::Client::new(&env, &usdc_address);
 
         // 5. Check vault has enough USDC.
         let vault_balance = usdc.balance(&env.current_contract_address());
         if vault_balance < amount {
-            panic!("insufficient USDC balance");
+            return Err(VaultError::InsufficientUsdcBalance);
         }
 
         // 6. Transfer USDC from vault to developer.
-        usdc.transfer(&env.current_contract_address(), &to, &amount);
+        let vault = env.current_contract_address();
+        Self::checked_transfer(&env, &usdc, &vault, &to, amount)?;
+        if to != vault {
+            Self::spend_revenue(&env, amount)?;
+        }
 
         // 7. Emit distribute event.
-        env.events()
-            .publish((Symbol::new(&env, "distribute"), to), amount);
+        Self::publish(&env, (Symbol::new(&env, "distribute"), to), amount);
+        Ok(())
     }
 
-    /// Get vault metadata (owner and balance).
-    pub fn get_meta(env: Env) -> VaultMeta {
-        env.storage()
-            .instance()
-            .get(&Symbol::new(&env, "meta"))
-            .unwrap_or_else(|| panic!("vault not initialized"))
+    /// Split `total` USDC between `recipients` by basis-point weight and transfer each
+    /// share. Weights must sum to 10000. Shares round down; the rounding remainder goes
+    /// to the first recipient with a non-zero weight, so exactly `total` leaves the vault
+    /// and a zero-weight recipient is never paid. Admin only. Like
+    /// `distribute`, the payout comes out of retained revenue first, and a share paid to the
+    /// vault itself stays in it.
+    ///
+    /// # Errors
+    /// * `Unauthorized`            – caller is not the admin.
+    /// * `AmountNotPositive`       – total is zero or negative.
+    /// * `EmptyBatch`              – no recipients.
+    /// * `InvalidConfig`           – a weight is above 10000, or weights do not sum to 10000.
+    /// * `InsufficientUsdcBalance` – vault holds less than total.
+    ///
+    /// # Events
+    /// Emits topic `("distribute", to)` with data `share` for each recipient.
+    pub fn distribute_many(
+        env: Env,
+        caller: Address,
+        recipients: Vec<(Address, u32)>,
+        total: i128,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if total <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        if recipients.is_empty() {
+            return Err(VaultError::EmptyBatch);
+        }
+        let mut weight_sum: u64 = 0;
+        for (_, bps) in recipients.iter() {
+            if bps > 10_000 {
+                return Err(VaultError::InvalidConfig);
+            }
+            weight_sum += bps as u64;
+        }
+        if weight_sum != 10_000 {
+            return Err(VaultError::InvalidConfig);
+        }
+
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        let vault = env.current_contract_address();
+        if usdc.balance(&vault) < total {
+            return Err(VaultError::InsufficientUsdcBalance);
+        }
+
+        let mut shares: Vec<i128> = Vec::new(&env);
+        for (_, bps) in recipients.iter() {
+            shares.push_back(total * bps as i128 / 10_000);
+        }
+        let remainder = total - shares.iter().sum::<i128>();
+        // Weights sum to 10000, so at least one is non-zero.
+        let first = recipients
+            .iter()
+            .position(|(_, bps)| bps > 0)
+            .unwrap_or_default() as u32;
+        shares.set(first, shares.get_unchecked(first) + remainder);
+
+        let mut paid_out = 0;
+        for ((to, _), share) in recipients.iter().zip(shares.iter()) {
+            if share > 0 {
+                Self::checked_transfer(&env, &usdc, &vault, &to, share)?;
+                if to != vault {
+                    paid_out += share;
+                }
+            }
+            Self::publish(&env, (Symbol::new(&env, "distribute"), to), share);
+        }
+        Self::spend_revenue(&env, paid_out)?;
+        Ok(())
+    }
+
+    /// Get vault metadata (owner, balance and the minimum deposit in force, including a
+    /// scheduled change that has taken effect).
+    /// Fails with `BalancePrivate` on a private-balance vault, since meta carries the balance.
+    pub fn get_meta(env: Env) -> Result<VaultMeta, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        let mut meta = Self::meta(&env)?;
+        meta.min_deposit = Self::get_effective_min_deposit(env)?;
+        Ok(meta)
     }
 
     /// Deposit increases balance. Callable by owner or designated depositor.
-    /// Panics if amount is below the configured minimum deposit.
-    /// Emits a "deposit" event with amount and new balance.
-    pub fn deposit(env: Env, amount: i128) -> i128 {
-        let mut meta = Self::get_meta(env.clone());
-        assert!(
-            amount >= meta.min_deposit,
-            "deposit below minimum: {} < {}",
-            amount,
-            meta.min_deposit
+    /// Fails with `BelowMinDeposit` if amount is below the minimum deposit in force, and
+    /// with `NotAValidTier` if deposit tiers are set and amount matches none of them.
+    /// Emits a "deposit" event with amount and new balance, then a "bonus" event if a
+    /// deposit bonus was credited on top.
+    pub fn deposit(env: Env, amount: i128) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        if amount < Self::get_effective_min_deposit(env.clone())? {
+            return Err(VaultError::BelowMinDeposit);
+        }
+        Self::require_deposit_tier(&env, amount)?;
+        Self::credit_deposit(&env, amount)
+    }
+
+    /// First phase of a two-phase deposit: transfer `amount` USDC from `from` into the
+    /// vault and hold it as pending, without crediting the balance. Returns the pending
+    /// deposit id for `confirm_deposit` or `cancel_deposit`.
+    /// Emits a "deposit_initiated" event with `from`, id, and amount.
+    pub fn initiate_deposit(env: Env, from: Address, amount: i128) -> Result<u32, VaultError> {
+        Self::require_initialized(&env)?;
+        from.require_auth();
+        if amount <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        if amount < Self::get_effective_min_deposit(env.clone())? {
+            return Err(VaultError::BelowMinDeposit);
+        }
+        Self::require_deposit_tier(&env, amount)?;
+
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        Self::checked_transfer(&env, &usdc, &from, &env.current_contract_address(), amount)?;
+
+        let seq_key = Symbol::new(&env, PENDING_DEPOSIT_SEQ_KEY);
+        let id: u32 = env.storage().instance().get(&seq_key).unwrap_or(0) + 1;
+        env.storage().instance().set(&seq_key, &id);
+        env.storage().persistent().set(
+            &(Symbol::new(&env, PENDING_DEPOSIT_KEY), id),
+            &PendingDeposit {
+                from: from.clone(),
+                amount,
+            },
         );
-        meta.balance += amount;
+        Self::add_pending_deposit_total(&env, amount);
+
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "deposit_initiated"), from),
+            (id, amount),
+        );
+        Ok(id)
+    }
+
+    /// Second phase: credit pending deposit `id` to the balance, exactly as `deposit`
+    /// would. Only the address that initiated it can confirm.
+    pub fn confirm_deposit(env: Env, from: Address, id: u32) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        from.require_auth();
+        let pending = Self::take_pending_deposit(&env, &from, id)?;
+        Self::credit_deposit(&env, pending.amount)
+    }
+
+    /// Abandon pending deposit `id` and refund its USDC to `from`, the address that
+    /// initiated it. Emits a "deposit_cancelled" event with `from`, id, and amount.
+    pub fn cancel_deposit(env: Env, from: Address, id: u32) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        from.require_auth();
+        let pending = Self::take_pending_deposit(&env, &from, id)?;
+
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        Self::checked_transfer(
+            &env,
+            &usdc,
+            &env.current_contract_address(),
+            &from,
+            pending.amount,
+        )?;
+
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "deposit_cancelled"), from),
+            (id, pending.amount),
+        );
+        Ok(pending.amount)
+    }
+
+    /// Return the balance together with what is still in flight and the balance it
+    /// projects to once everything settles.
+    pub fn net_position(env: Env) -> Result<NetPosition, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        let settled = Self::meta(&env)?.balance;
+        let pending_in = Self::pending_deposit_total(&env);
+        let (pending_out, holds) = (0, 0);
+        Ok(NetPosition {
+            settled,
+            pending_in,
+            pending_out,
+            holds,
+            projected: settled + pending_in - pending_out - holds,
+        })
+    }
+
+    /// Return pending deposit `id`, or `None` if it was confirmed, cancelled, or never existed.
+    pub fn pending_deposit(env: Env, id: u32) -> Result<Option<PendingDeposit>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .get(&(Symbol::new(&env, PENDING_DEPOSIT_KEY), id)))
+    }
+
+    /// Return the USDC held in pending deposits, not yet credited to the balance.
+    pub fn total_pending_deposits(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(Self::pending_deposit_total(&env))
+    }
+
+    /// Restrict deposits to these exact amounts (fixed-price credit packs). Owner only;
+    /// an empty list allows any amount.
+    pub fn set_deposit_tiers(
+        env: Env,
+        caller: Address,
+        tiers: Vec<i128>,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        if caller != Self::meta(&env)?.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        if tiers.iter().any(|tier| tier <= 0) {
+            return Err(VaultError::InvalidConfig);
+        }
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "meta"), &meta);
+            .set(&Symbol::new(&env, DEPOSIT_TIERS_KEY), &tiers);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
 
-        env.events()
-            .publish((Symbol::new(&env, "deposit"),), (amount, meta.balance));
-        meta.balance
+    /// Return the allowed deposit amounts (empty = any amount).
+    pub fn get_deposit_tiers(env: Env) -> Result<Vec<i128>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEPOSIT_TIERS_KEY))
+            .unwrap_or_else(|| Vec::new(&env)))
     }
 
-    /// Deduct balance for an API call. Callable by authorized caller (e.g. backend/deployer).
-    /// Emits a "deduct" event with caller, optional request_id, amount, and new balance.
-    pub fn deduct(env: Env, caller: Address, amount: i128, request_id: Option<Symbol>) -> i128 {
-        caller.require_auth();
-        let mut meta = Self::get_meta(env.clone());
-        assert!(meta.balance >= amount, "insufficient balance");
-        meta.balance -= amount;
+    /// Credit `bps` basis points extra on every deposit, up to `cap` in total bonuses
+    /// paid. Admin only. Bonuses come out of USDC the vault holds beyond what it owes,
+    /// so the owner pre-funds them; `bps = 0` turns the promotion off.
+    pub fn set_deposit_bonus_bps(
+        env: Env,
+        caller: Address,
+        bps: u32,
+        cap: i128,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if bps > 10_000 || cap < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "meta"), &meta);
+            .set(&Symbol::new(&env, DEPOSIT_BONUS_KEY), &(bps, cap));
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
 
-        let topics = match &request_id {
-            Some(rid) => (Symbol::new(&env, "deduct"), caller.clone(), rid.clone()),
-            None => (
-                Symbol::new(&env, "deduct"),
-                caller.clone(),
-                Symbol::new(&env, ""),
-            ),
-        };
-        env.events().publish(topics, (amount, meta.balance));
-        meta.balance
+    /// Return the deposit bonus `(bps, cap)`, or `None` if no promotion was set.
+    pub fn get_deposit_bonus(env: Env) -> Result<Option<(u32, i128)>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEPOSIT_BONUS_KEY)))
     }
 
-    /// Batch deduct: multiple (amount, optional request_id) in one transaction.
-    /// Reverts the entire batch if any single deduct would exceed balance.
-    /// Emits one "deduct" event per item (same shape as single deduct).
-    pub fn batch_deduct(env: Env, caller: Address, items: Vec<DeductItem>) -> i128 {
-        caller.require_auth();
-        let mut meta = Self::get_meta(env.clone());
-        let n = items.len();
-        assert!(n > 0, "batch_deduct requires at least one item");
+    /// Return the total deposit bonus credited so far.
+    pub fn get_bonus_paid(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, BONUS_PAID_KEY))
+            .unwrap_or(0))
+    }
 
-        // Validate: running balance must never go negative
-        let mut running = meta.balance;
-        for item in items.iter() {
-            assert!(item.amount > 0, "amount must be positive");
-            assert!(running >= item.amount, "insufficient balance");
-            running -= item.amount;
+    /// Schedule `new_min` to replace the minimum deposit once the ledger timestamp
+    /// reaches `effective_ts`. Admin only. Until then the current minimum still
+    /// applies; a later call replaces any change that has not yet taken effect.
+    pub fn set_min_deposit_effective_at(
+        env: Env,
+        caller: Address,
+        new_min: i128,
+        effective_ts: u64,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if new_min < 0 {
+            return Err(VaultError::InvalidConfig);
         }
 
-        // Apply all deductions and emit one event per deduct
-        let mut balance = meta.balance;
-        for item in items.iter() {
-            balance -= item.amount;
-            let topics = match &item.request_id {
-                Some(rid) => (Symbol::new(&env, "deduct"), caller.clone(), rid.clone()),
-                None => (
-                    Symbol::new(&env, "deduct"),
-                    caller.clone(),
-                    Symbol::new(&env, ""),
-                ),
-            };
-            env.events().publish(topics, (item.amount, balance));
+        // Fold a change that has already taken effect into meta before replacing it.
+        let mut meta = Self::meta(&env)?;
+        meta.min_deposit = Self::get_effective_min_deposit(env.clone())?;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, META_KEY), &meta);
+        env.storage().instance().set(
+            &Symbol::new(&env, PENDING_MIN_DEPOSIT_KEY),
+            &(new_min, effective_ts),
+        );
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the minimum deposit a `deposit` must meet (0 = none). This is the value
+    /// stored at `init`, or a scheduled replacement once it has taken effect.
+    pub fn get_min_deposit(env: Env) -> Result<i128, VaultError> {
+        Self::get_effective_min_deposit(env)
+    }
+
+    /// Return the minimum deposit in force at the current ledger timestamp.
+    pub fn get_effective_min_deposit(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        let pending: Option<(i128, u64)> = env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, PENDING_MIN_DEPOSIT_KEY));
+        match pending {
+            Some((new_min, effective_ts)) if env.ledger().timestamp() >= effective_ts => {
+                Ok(new_min)
+            }
+            _ => Ok(Self::meta(&env)?.min_deposit),
         }
+    }
 
-        meta.balance = balance;
+    /// Set the deduct shortfall tolerance. Admin only; 0 disables the grace.
+    pub fn set_deduct_tolerance(env: Env, caller: Address, amount: i128) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "meta"), &meta);
-        meta.balance
+            .set(&Symbol::new(&env, DEDUCT_TOLERANCE_KEY), &amount);
+        Self::record_config_change(&env)?;
+        Ok(())
     }
 
-    /// Withdraw from vault. Callable only by the vault owner; reduces balance.
-    /// When USDC is integrated, funds will be transferred to the owner.
-    pub fn withdraw(env: Env, amount: i128) -> i128 {
-        let mut meta = Self::get_meta(env.clone());
-        meta.owner.require_auth();
-        assert!(amount > 0, "amount must be positive");
-        assert!(meta.balance >= amount, "insufficient balance");
-        meta.balance -= amount;
+    /// Return the deduct shortfall tolerance (0 if never set).
+    pub fn get_deduct_tolerance(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEDUCT_TOLERANCE_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Set a fixed fee charged on top of every deduct's `amount`. Admin only; 0 disables it.
+    pub fn set_deduct_base_fee(env: Env, caller: Address, base: i128) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if base < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "meta"), &meta);
+            .set(&Symbol::new(&env, DEDUCT_BASE_FEE_KEY), &base);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
 
-        env.events().publish(
-            (Symbol::new(&env, "withdraw"), meta.owner.clone()),
-            (amount, meta.balance),
-        );
-        meta.balance
+    /// Return the fixed fee added to each deduct (0 if never set).
+    pub fn get_deduct_base_fee(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEDUCT_BASE_FEE_KEY))
+            .unwrap_or(0))
     }
 
-    /// Withdraw from vault to a designated address. Owner-only.
-    /// When USDC is integrated, funds will be transferred to `to`.
-    pub fn withdraw_to(env: Env, to: Address, amount: i128) -> i128 {
-        let mut meta = Self::get_meta(env.clone());
-        meta.owner.require_auth();
-        assert!(amount > 0, "amount must be positive");
-        assert!(meta.balance >= amount, "insufficient balance");
-        meta.balance -= amount;
+    /// Cap each deduct, base fee included, at `bps` basis points of the balance at the
+    /// time of the deduct. Admin only; 0 removes the cap. Fails with `InvalidConfig` above 10000.
+    pub fn set_max_deduct_bps(env: Env, caller: Address, bps: u32) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if bps > 10_000 {
+            return Err(VaultError::InvalidConfig);
+        }
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "meta"), &meta);
+            .set(&Symbol::new(&env, MAX_DEDUCT_BPS_KEY), &bps);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
 
-        env.events().publish(
-            (
-                Symbol::new(&env, "withdraw_to"),
-                meta.owner.clone(),
-                to.clone(),
-            ),
-            (amount, meta.balance),
+    /// Return the per-deduct cap set by `set_max_deduct_bps` (0 if never set).
+    pub fn get_max_deduct_bps(env: Env) -> Result<u32, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, MAX_DEDUCT_BPS_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Return the largest total (base fee plus amount) a deduct may charge right now, or
+    /// `None` when no percentage cap is set.
+    pub fn effective_max_deduct(env: Env) -> Result<Option<i128>, VaultError> {
+        let balance = Self::meta(&env)?.balance;
+        Self::max_deduct_for(&env, balance)
+    }
+
+    /// Limit how many deducts (batch items included) may run within one ledger; further
+    /// deducts in that ledger fail with `TooManyDeducts`. Admin only; 0 removes the limit.
+    pub fn set_max_deducts_per_ledger(env: Env, caller: Address, n: u32) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, MAX_LEDGER_DEDUCTS_KEY), &n);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the per-ledger deduct limit (0 if never set).
+    pub fn get_max_deducts_per_ledger(env: Env) -> Result<u32, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, MAX_LEDGER_DEDUCTS_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Cap the total deducted per calendar month (UTC, from the ledger timestamp). A
+    /// deduct that would go past it fails with `MonthlyBudgetExceeded`; usage starts
+    /// over at each month boundary. Admin only; 0 removes the budget.
+    pub fn set_monthly_deduct_budget(
+        env: Env,
+        caller: Address,
+        amount: i128,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, MONTHLY_BUDGET_KEY), &amount);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the monthly deduct budget (0 if never set).
+    pub fn get_monthly_deduct_budget(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, MONTHLY_BUDGET_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Return the amount deducted so far in the current calendar month while a monthly
+    /// budget is set.
+    pub fn monthly_deduct_used(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        let month = Self::month_index(env.ledger().timestamp());
+        Ok(
+            match env
+                .storage()
+                .instance()
+                .get::<_, (u32, i128)>(&Symbol::new(&env, MONTHLY_USED_KEY))
+            {
+                Some((used_month, used)) if used_month == month => used,
+                _ => 0,
+            },
+        )
+    }
+
+    /// Guard against a compromised deductor: within each `window_secs` window, deducts
+    /// may total at most `bps` basis points of the deposits made in that window, or fail
+    /// with `DeductDepositRatioExceeded`. Windows are fixed-length and start at the
+    /// first tracked deposit or deduct after the previous one ends. Admin only; `bps = 0`
+    /// removes the guard.
+    pub fn set_max_deduct_deposit_ratio(
+        env: Env,
+        caller: Address,
+        bps: u32,
+        window_secs: u64,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        let key = Symbol::new(&env, RATIO_GUARD_KEY);
+        if bps == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            if window_secs == 0 {
+                return Err(VaultError::InvalidConfig);
+            }
+            env.storage().instance().set(&key, &(bps, window_secs));
+        }
+        env.storage()
+            .instance()
+            .remove(&Symbol::new(&env, RATIO_WINDOW_KEY));
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the deduct/deposit ratio guard as `(bps, window_secs)`, if set.
+    pub fn get_max_deduct_deposit_ratio(env: Env) -> Result<Option<(u32, u64)>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, RATIO_GUARD_KEY)))
+    }
+
+    /// Return deducts as basis points of deposits in the current ratio window, or `None`
+    /// if the guard is off or nothing has been deposited in the window.
+    pub fn deduct_deposit_ratio(env: Env) -> Result<Option<u32>, VaultError> {
+        let Some((_, window_secs)) = Self::get_max_deduct_deposit_ratio(env.clone())? else {
+            return Ok(None);
+        };
+        let (_, deposited, deducted) = Self::ratio_window(&env, window_secs);
+        if deposited == 0 {
+            return Ok(None);
+        }
+        Ok(Some(
+            u32::try_from(deducted * 10_000 / deposited).unwrap_or(u32::MAX),
+        ))
+    }
+
+    /// Deduct balance for an API call. Callable by authorized caller (e.g. backend/deployer).
+    /// The base fee, if set, is charged on top of `amount`.
+    /// If `amount` exceeds the balance by at most the deduct tolerance, the whole balance is
+    /// charged instead and the shortfall is reported in the event.
+    /// Fails with `CosignRequired` if `amount` is above the cosign threshold, and with
+    /// `InBlackout` inside the deduct blackout window. Once any reason code is registered,
+    /// `reason` must be one of them (`UnknownReason` otherwise). Fails with `DeadlinePassed`
+    /// if `deadline` is set and the ledger timestamp is past it.
+    /// Emits a "deduct" event with caller, optional request_id, optional reason, amount,
+    /// and new balance, then a "base_fee" event splitting the charge when a base fee is set.
+    pub fn deduct(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        request_id: Option<Symbol>,
+        reason: Option<Symbol>,
+        deadline: Option<u64>,
+    ) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        Self::apply_deduct(&env, caller, amount, request_id, reason, deadline, false)
+    }
+
+    /// `deduct` that also returns a receipt: the sha256 of the XDR of
+    /// `(caller, amount, request_id, nonce, balance_after)`, where `nonce` counts receipts
+    /// issued by this vault from 0. Emits a "receipt" event carrying the hash after the
+    /// "deduct" event, so the client can store it as proof of the charge.
+    pub fn deduct_with_receipt(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        request_id: Option<Symbol>,
+    ) -> Result<BytesN<32>, VaultError> {
+        let balance_after = Self::deduct(
+            env.clone(),
+            caller.clone(),
+            amount,
+            request_id.clone(),
+            None,
+            None,
+        )?;
+        let key = Symbol::new(&env, RECEIPT_NONCE_KEY);
+        let nonce: u64 = env.storage().instance().get(&key).unwrap_or(0);
+        env.storage().instance().set(&key, &(nonce + 1));
+
+        let preimage = (
+            caller.clone(),
+            amount,
+            request_id.clone(),
+            nonce,
+            balance_after,
         );
-        meta.balance
+        let receipt: BytesN<32> = env.crypto().sha256(&preimage.to_xdr(&env)).into();
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "receipt"), caller, request_id),
+            receipt.clone(),
+        );
+        Ok(receipt)
     }
 
-    /// Return current balance.
-    pub fn balance(env: Env) -> i128 {
-        Self::get_meta(env).balance
+    /// `deduct` for an ordered billing stream. `seq` must be exactly one more than
+    /// `last_seq(namespace)` (so the first is 1); a duplicate, reordered or skipped seq
+    /// fails with `OutOfOrderSeq`, which exposes dropped or reordered messages.
+    pub fn deduct_seq(
+        env: Env,
+        caller: Address,
+        namespace: Symbol,
+        seq: u64,
+        amount: i128,
+        request_id: Option<Symbol>,
+    ) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        if seq != Self::last_seq(env.clone(), namespace.clone())?.saturating_add(1) {
+            return Err(VaultError::OutOfOrderSeq);
+        }
+        let balance = Self::deduct(env.clone(), caller, amount, request_id, None, None)?;
+        env.storage()
+            .persistent()
+            .set(&(Symbol::new(&env, SEQ_KEY), namespace), &seq);
+        Ok(balance)
     }
-}
+
+    /// Return the last seq accepted by `deduct_seq` for `namespace` (0 if none).
+    pub fn last_seq(env: Env, namespace: Symbol) -> Result<u64, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .get(&(Symbol::new(&env, SEQ_KEY), namespace))
+            .unwrap_or(0))
+    }
+
+    /// Deduct that also carries the configured cosigner's approval, for amounts above the
+    /// cosign threshold. Both `caller` and `cosigner` must authorize. Otherwise behaves
+    /// like `deduct`.
+    pub fn deduct_cosigned(
+        env: Env,
+        caller: Address,
+        cosigner: Address,
+        amount: i128,
+        request_id: Option<Symbol>,
+        reason: Option<Symbol>,
+    ) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        cosigner.require_auth();
+        match Self::get_deduct_cosign(env.clone())? {
+            Some((_, configured)) if configured == cosigner => {}
+            _ => return Err(VaultError::Unauthorized),
+        }
+        Self::apply_deduct(&env, caller, amount, request_id, reason, None, true)
+    }
+
+    /// Require `cosigner` to approve deducts above `amount`. Admin only.
+    pub fn set_deduct_cosign_threshold(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        cosigner: Address,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, DEDUCT_COSIGN_KEY), &(amount, cosigner));
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the cosign `(threshold, cosigner)`, or `None` if deducts need no cosigner.
+    pub fn get_deduct_cosign(env: Env) -> Result<Option<(i128, Address)>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEDUCT_COSIGN_KEY)))
+    }
+
+    /// Reject deducts while the ledger timestamp is in `[start_ts, end_ts)`. Admin only;
+    /// replaces any earlier window.
+    pub fn set_deduct_blackout(
+        env: Env,
+        caller: Address,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if start_ts >= end_ts {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, DEDUCT_BLACKOUT_KEY), &(start_ts, end_ts));
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Remove the deduct blackout window. Admin only.
+    pub fn clear_deduct_blackout(env: Env, caller: Address) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        env.storage()
+            .instance()
+            .remove(&Symbol::new(&env, DEDUCT_BLACKOUT_KEY));
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the deduct blackout window `(start_ts, end_ts)`, if one is set.
+    pub fn get_deduct_blackout(env: Env) -> Result<Option<(u64, u64)>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEDUCT_BLACKOUT_KEY)))
+    }
+
+    /// Add `code` to the deduct reason registry, or update its description. Admin only.
+    /// While the registry is empty, `deduct` accepts any reason.
+    pub fn register_reason(
+        env: Env,
+        caller: Address,
+        code: Symbol,
+        description: String,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        let mut codes = Self::list_reasons(env.clone())?;
+        if !codes.contains(&code) {
+            codes.push_back(code.clone());
+            env.storage()
+                .instance()
+                .set(&Symbol::new(&env, REASONS_KEY), &codes);
+        }
+        env.storage()
+            .persistent()
+            .set(&(Symbol::new(&env, REASON_KEY), code), &description);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the registered deduct reason codes, in registration order.
+    pub fn list_reasons(env: Env) -> Result<Vec<Symbol>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, REASONS_KEY))
+            .unwrap_or_else(|| Vec::new(&env)))
+    }
+
+    /// Return the description registered for reason `code`, if any.
+    pub fn reason_description(env: Env, code: Symbol) -> Result<Option<String>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .get(&(Symbol::new(&env, REASON_KEY), code)))
+    }
+
+    /// Emit a "low_balance" event the first time a deduct leaves the balance below
+    /// `amount`, so the backend can top the vault up. Admin only; 0 turns it off.
+    pub fn set_low_balance_threshold(
+        env: Env,
+        caller: Address,
+        amount: i128,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, LOW_BALANCE_THRESHOLD_KEY), &amount);
+        env.storage()
+            .instance()
+            .remove(&Symbol::new(&env, LOW_BALANCE_SIGNALED_KEY));
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the low-balance signal threshold (0 if never set).
+    pub fn get_low_balance_threshold(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, LOW_BALANCE_THRESHOLD_KEY))
+            .unwrap_or(0))
+    }
+
+    /// When `restricted` is true, only the owner or admin may deduct; any other caller
+    /// gets `Unauthorized`. Admin only. Open (false) by default.
+    pub fn set_deduct_restricted(
+        env: Env,
+        caller: Address,
+        restricted: bool,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, DEDUCT_RESTRICTED_KEY), &restricted);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return whether deducts are restricted to the owner and admin.
+    pub fn is_deduct_restricted(env: Env) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, DEDUCT_RESTRICTED_KEY))
+            .unwrap_or(false))
+    }
+
+    /// Route deducts through a policy contract chosen by the owner. When set, every
+    /// deduct calls the policy's `authorize(caller, amount, request_id) -> bool` and
+    /// fails with `PolicyRejected` if it returns false. `None` removes the policy.
+    /// Owner only.
+    pub fn set_auth_policy(
+        env: Env,
+        caller: Address,
+        policy: Option<Address>,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        if caller != Self::meta(&env)?.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        let key = Symbol::new(&env, AUTH_POLICY_KEY);
+        match policy {
+            Some(policy) => env.storage().instance().set(&key, &policy),
+            None => env.storage().instance().remove(&key),
+        }
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the auth policy contract deducts are checked against, if any.
+    pub fn get_auth_policy(env: Env) -> Result<Option<Address>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, AUTH_POLICY_KEY)))
+    }
+
+    /// Return what `deduct` would emit for these inputs without changing state.
+    /// Runs the same checks as `deduct` (auth aside), so it fails exactly as `deduct`
+    /// would if the deduct cannot go through.
+    pub fn preview_deduct_event(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        request_id: Option<Symbol>,
+        reason: Option<Symbol>,
+        deadline: Option<u64>,
+    ) -> Result<DeductEventPreview, VaultError> {
+        Self::require_initialized(&env)?;
+        let request_id = Self::normalize_request_id(&env, request_id);
+        let (base_fee, charged, shortfall) =
+            Self::validate_deduct(&env, &caller, amount, &request_id, &reason, deadline, false)?;
+        let balance = Self::meta(&env)?.balance;
+        Ok(DeductEventPreview {
+            caller,
+            request_id,
+            reason,
+            amount: charged,
+            new_balance: balance - charged,
+            shortfall,
+            base_fee,
+            variable: amount,
+        })
+    }
+
+    /// Run the per-item checks of `batch_deduct` against a simulated balance, without
+    /// auth or state changes. A failing item does not reduce the simulated balance, so
+    /// later items show whether they would pass on their own. Batch-wide failures
+    /// (blackout, restricted caller, empty batch) are returned as an error.
+    pub fn simulate_batch_deduct(
+        env: Env,
+        caller: Address,
+        items: Vec<DeductItem>,
+    ) -> Result<Vec<ItemOutcome>, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_no_blackout(&env)?;
+        Self::require_authorized_deductor(&env, &caller)?;
+        if items.is_empty() {
+            return Err(VaultError::EmptyBatch);
+        }
+
+        let threshold = Self::get_deduct_cosign(env.clone())?.map(|(threshold, _)| threshold);
+        let interval = Self::get_request_id_min_interval(env.clone())?;
+        let base = Self::get_deduct_base_fee(env.clone())?;
+        let mut ledger_used = Self::ledger_deducts_used(&env);
+        let mut month_used = Self::monthly_deduct_used(env.clone())?;
+        let (_, deposited, mut window_deducted) = Self::get_max_deduct_deposit_ratio(env.clone())?
+            .map(|(_, window_secs)| Self::ratio_window(&env, window_secs))
+            .unwrap_or_default();
+        let mut seen: Vec<Symbol> = Vec::new(&env);
+        let mut balance = Self::meta(&env)?.balance;
+        let mut outcomes = Vec::new(&env);
+        for item in items.iter() {
+            let request_id = Self::normalize_request_id(&env, item.request_id);
+            let error = if item.amount <= 0 {
+                Some(VaultError::AmountNotPositive)
+            } else if threshold.is_some_and(|threshold| item.amount > threshold) {
+                Some(VaultError::CosignRequired)
+            } else if let Err(e) = Self::require_ledger_deducts_within_limit(&env, ledger_used + 1)
+            {
+                Some(e)
+            } else if Self::max_deduct_for(&env, balance)?
+                .is_some_and(|max| base + item.amount > max)
+            {
+                Some(VaultError::AboveMaxDeduct)
+            } else if balance < base + item.amount {
+                Some(VaultError::InsufficientBalance)
+            } else {
+                match &request_id {
+                    Some(rid) if interval > 0 && seen.contains(rid) => {
+                        Some(VaultError::RequestIdTooSoon)
+                    }
+                    Some(rid) => Self::require_request_id_interval(&env, rid).err(),
+                    None => None,
+                }
+                .or_else(|| Self::require_request_id_allowed(&env, &request_id).err())
+                .or_else(|| {
+                    Self::require_policy_approval(&env, &caller, item.amount, &request_id).err()
+                })
+            }
+            .or_else(|| {
+                Self::require_within_monthly_budget(&env, month_used + base + item.amount).err()
+            })
+            .or_else(|| {
+                let deducted = window_deducted + base + item.amount;
+                Self::require_within_deduct_ratio(&env, deposited, deducted).err()
+            });
+            if error.is_none() {
+                balance -= base + item.amount;
+                ledger_used += 1;
+                month_used += base + item.amount;
+                window_deducted += base + item.amount;
+                if let Some(rid) = &request_id {
+                    seen.push_back(rid.clone());
+                }
+            }
+            outcomes.push_back(ItemOutcome {
+                request_id,
+                balance_after: balance,
+                error: error.map(|e| e as u32),
+            });
+        }
+        Ok(outcomes)
+    }
+
+    /// Batch deduct: multiple (amount, optional request_id) in one transaction.
+    /// Reverts the entire batch if any single deduct would exceed balance.
+    /// Emits one "deduct" event per item (same shape as single deduct).
+    pub fn batch_deduct(
+        env: Env,
+        caller: Address,
+        items: Vec<DeductItem>,
+    ) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        Self::require_no_blackout(&env)?;
+        Self::require_authorized_deductor(&env, &caller)?;
+        let mut meta = Self::meta(&env)?;
+        if items.is_empty() {
+            return Err(VaultError::EmptyBatch);
+        }
+
+        Self::count_ledger_deducts(&env, items.len())?;
+        let threshold = Self::get_deduct_cosign(env.clone())?.map(|(threshold, _)| threshold);
+        let base = Self::get_deduct_base_fee(env.clone())?;
+
+        // Validate: running balance must never go negative
+        let mut running = meta.balance;
+        for item in items.iter() {
+            if item.amount <= 0 {
+                return Err(VaultError::AmountNotPositive);
+            }
+            if threshold.is_some_and(|threshold| item.amount > threshold) {
+                return Err(VaultError::CosignRequired);
+            }
+            if Self::max_deduct_for(&env, running)?.is_some_and(|max| base + item.amount > max) {
+                return Err(VaultError::AboveMaxDeduct);
+            }
+            if running < base + item.amount {
+                return Err(VaultError::InsufficientBalance);
+            }
+            let request_id = Self::normalize_request_id(&env, item.request_id);
+            Self::require_request_id_allowed(&env, &request_id)?;
+            Self::require_policy_approval(&env, &caller, item.amount, &request_id)?;
+            running -= base + item.amount;
+        }
+
+        // Apply all deductions and emit one event per deduct
+        let mut balance = meta.balance;
+        for item in items.iter() {
+            let total = base + item.amount;
+            balance -= total;
+            let request_id = Self::normalize_request_id(&env, item.request_id);
+            if let Some(rid) = &request_id {
+                Self::require_request_id_interval(&env, rid)?;
+                Self::record_request_id(&env, rid);
+                Self::add_deduct_amount(&env, rid, total);
+            }
+            Self::log_deduct(&env, &caller, total, balance, &request_id);
+            let topics = (
+                Symbol::new(&env, "deduct"),
+                caller.clone(),
+                request_id.clone(),
+                None::<Symbol>,
+            );
+            Self::publish(&env, topics, (total, balance));
+            Self::publish_base_fee(&env, &caller, request_id, base, item.amount);
+        }
+
+        Self::spend_monthly_budget(&env, meta.balance - balance)?;
+        Self::track_deduct_deposit_ratio(&env, 0, meta.balance - balance)?;
+        Self::add_revenue(&env, meta.balance - balance);
+        meta.balance = balance;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, META_KEY), &meta);
+        Self::signal_low_balance(&env, meta.balance)?;
+        Ok(meta.balance)
+    }
+
+    /// Charge in arrears: add `amount` to the unpaid tab without touching `meta.balance`.
+    /// The resulting tab may not exceed `tab_limit` (0 until the admin sets one).
+    /// The caller checks of `deduct` apply: the blackout window, restricted deducts and
+    /// the request-id blocklist.
+    /// Emits an "accrue" event with caller, optional request_id, amount, and new tab.
+    pub fn accrue(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        request_id: Option<Symbol>,
+    ) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        let request_id = Self::normalize_request_id(&env, request_id);
+        caller.require_auth();
+        if amount <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        Self::require_no_blackout(&env)?;
+        Self::require_authorized_deductor(&env, &caller)?;
+        Self::require_request_id_allowed(&env, &request_id)?;
+        let tab = Self::tab_balance(env.clone())? + amount;
+        if tab > Self::tab_limit(env.clone())? {
+            return Err(VaultError::TabLimitExceeded);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, TAB_KEY), &tab);
+
+        if let Some(rid) = &request_id {
+            env.storage().persistent().set(
+                &(Symbol::new(&env, ACCRUAL_ID_KEY), rid.clone()),
+                &env.ledger().timestamp(),
+            );
+        }
+
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "accrue"), caller, request_id),
+            (amount, tab),
+        );
+        Ok(tab)
+    }
+
+    /// Settle the whole unpaid tab by transferring that much USDC from `from` to the vault,
+    /// then reset the tab to zero. Returns the amount settled (0 if there was no tab).
+    /// Emits a "settle_tab" event with caller, `from`, and the amount settled.
+    pub fn settle_tab(env: Env, caller: Address, from: Address) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        from.require_auth();
+        let tab = Self::tab_balance(env.clone())?;
+        if tab == 0 {
+            return Ok(0);
+        }
+
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        Self::checked_transfer(&env, &usdc, &from, &env.current_contract_address(), tab)?;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, TAB_KEY), &0i128);
+
+        Self::publish(&env, (Symbol::new(&env, "settle_tab"), caller, from), tab);
+        Ok(tab)
+    }
+
+    /// Return the unpaid tab accrued since the last settlement.
+    pub fn tab_balance(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, TAB_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Set the maximum unpaid tab. Admin only; 0 disables accrual.
+    pub fn set_tab_limit(env: Env, caller: Address, limit: i128) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if limit < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, TAB_LIMIT_KEY), &limit);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the maximum unpaid tab (0 if never set).
+    pub fn tab_limit(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, TAB_LIMIT_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Check whether `withdraw(amount)` would go through right now, without auth or
+    /// state changes. Lets a UI enable the withdraw button only when it would succeed.
+    pub fn can_withdraw(env: Env, amount: i128) -> Result<WithdrawEligibility, VaultError> {
+        Self::require_initialized(&env)?;
+        let balance = Self::meta(&env)?.balance;
+        let error = Self::require_withdrawable(&env, balance, amount).err();
+        Ok(WithdrawEligibility {
+            allowed: error.is_none(),
+            error: error.map(|e| e as u32),
+        })
+    }
+
+    /// Withdraw from vault. Callable only by the vault owner; reduces balance.
+    /// When USDC is integrated, funds will be transferred to the owner.
+    pub fn withdraw(env: Env, amount: i128) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        let mut meta = Self::meta(&env)?;
+        meta.owner.require_auth();
+        Self::require_withdrawable(&env, meta.balance, amount)?;
+        meta.balance -= amount;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, META_KEY), &meta);
+
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "withdraw"), meta.owner.clone()),
+            (amount, meta.balance, meta.owner.clone()),
+        );
+        Ok(meta.balance)
+    }
+
+    /// Set the smallest amount `withdraw` and `withdraw_to` accept, to keep dust
+    /// withdrawals out. Admin only; 0 disables the minimum.
+    pub fn set_min_withdraw(env: Env, caller: Address, amount: i128) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount < 0 {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, MIN_WITHDRAW_KEY), &amount);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the minimum withdrawal amount (0 if never set).
+    pub fn get_min_withdraw(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, MIN_WITHDRAW_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Withdraw from vault to a designated address. Owner-only.
+    /// When USDC is integrated, funds will be transferred to `to`.
+    pub fn withdraw_to(env: Env, to: Address, amount: i128) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        let mut meta = Self::meta(&env)?;
+        meta.owner.require_auth();
+        Self::require_withdrawable(&env, meta.balance, amount)?;
+        meta.balance -= amount;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, META_KEY), &meta);
+
+        Self::publish(
+            &env,
+            (
+                Symbol::new(&env, "withdraw_to"),
+                meta.owner.clone(),
+                to.clone(),
+            ),
+            (amount, meta.balance, to),
+        );
+        Ok(meta.balance)
+    }
+
+    /// Compare the vault's USDC holdings with what it owes (`required_reserve()`) so an
+    /// auditor can check `assets >= liabilities` on-chain. Fails with `BalancePrivate` on
+    /// a private-balance vault; `solvency_ok()` still answers there.
+    pub fn solvency(env: Env) -> Result<Solvency, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        let assets = usdc.balance(&env.current_contract_address());
+        let liabilities = Self::reserve(&env)?;
+        Ok(Solvency {
+            assets,
+            liabilities,
+            surplus: assets - liabilities,
+        })
+    }
+
+    /// Return the part of the vault's funds backed by deposits: the owner's credit.
+    /// Fails with `BalancePrivate` on a private-balance vault.
+    pub fn deposit_backed_balance(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        Ok(Self::meta(&env)?.balance)
+    }
+
+    /// Return revenue earned by deducts and still held in the vault: everything charged,
+    /// less partial refunds and `withdraw_revenue` payouts.
+    pub fn retained_revenue(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, REVENUE_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Transfer `amount` of retained revenue to `caller`. Admin only. Fails with
+    /// `InsufficientBalance` above `retained_revenue()`, and with `InsufficientUsdcBalance`
+    /// if the USDC left would no longer cover `required_reserve()`, so deposit-backed funds
+    /// can never leave this way. Emits a "withdraw_revenue" event with the admin, amount and the
+    /// revenue left.
+    pub fn withdraw_revenue(env: Env, caller: Address, amount: i128) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        let revenue = Self::retained_revenue(env.clone())?;
+        if amount > revenue {
+            return Err(VaultError::InsufficientBalance);
+        }
+        Self::add_revenue(&env, -amount);
+
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        let vault = env.current_contract_address();
+        if usdc.balance(&vault) - amount < Self::reserve(&env)? {
+            return Err(VaultError::InsufficientUsdcBalance);
+        }
+        Self::checked_transfer(&env, &usdc, &vault, &caller, amount)?;
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "withdraw_revenue"), caller),
+            (amount, revenue - amount),
+        );
+        Ok(revenue - amount)
+    }
+
+    /// Minimum USDC the vault must hold to honour everything it owes: the owner's
+    /// credit, pending deposits that may still be refunded, and retained revenue. Fails
+    /// with `BalancePrivate` on a private-balance vault.
+    pub fn required_reserve(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        Self::reserve(&env)
+    }
+
+    /// Return whether the vault's USDC holdings cover `required_reserve()`.
+    pub fn solvency_ok(env: Env) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        let required = Self::reserve(&env)?;
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        Ok(usdc.balance(&env.current_contract_address()) >= required)
+    }
+
+    /// Return how much USDC `from` has currently approved the vault to pull.
+    /// Lets a frontend prompt for re-approval before a token pull fails.
+    pub fn check_approval(env: Env, from: Address) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        let usdc = token::Client::new(&env, &Self::usdc_address(&env)?);
+        Ok(usdc.allowance(&from, &env.current_contract_address()))
+    }
+
+    /// Return current balance.
+    /// Fails with `BalancePrivate` if the vault was initialized with a private balance.
+    pub fn balance(env: Env) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        Ok(Self::meta(&env)?.balance)
+    }
+
+    /// Return current balance to the owner. Requires the owner's signature.
+    /// This only hides the balance from contract reads; events and ledger state stay public.
+    pub fn private_balance(env: Env, caller: Address) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        caller.require_auth();
+        let meta = Self::meta(&env)?;
+        if caller != meta.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        Ok(meta.balance)
+    }
+
+    /// Return whether the public balance reads (`balance()`, `get_meta()` and the
+    /// views derived from the balance) are disabled.
+    pub fn is_balance_private(env: Env) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, BALANCE_PRIVATE_KEY))
+            .unwrap_or(false))
+    }
+
+    /// Turn event emission on or off. Admin only. While disabled, every entry point
+    /// skips publishing and callers rely on return values for observability.
+    pub fn set_events_enabled(env: Env, caller: Address, enabled: bool) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, EVENTS_ENABLED_KEY), &enabled);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Write operations to the on-chain op log instead of emitting events, keeping the
+    /// last `size` records. Admin only; 0 (the default) turns the log off and restores
+    /// normal event emission. `set_events_enabled(false)` still silences both. Fails
+    /// with `InvalidConfig` above 100.
+    pub fn set_op_log_size(env: Env, caller: Address, size: u32) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if size > MAX_OP_LOG_SIZE {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, OP_LOG_SIZE_KEY), &size);
+        Self::trim_log(&env, OP_LOG_KEY, OP_LOG_POS_KEY, size);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return how many records the op log keeps (0 = off, events are emitted).
+    pub fn op_log_size(env: Env) -> Result<u32, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(Self::op_log_capacity(&env))
+    }
+
+    /// Page through the op log, newest first, as one `Bytes` blob. Each record is a
+    /// 4-byte big-endian length followed by the XDR of `(topics, data)` for the event
+    /// it replaced.
+    pub fn op_log(env: Env, offset: u32, limit: u32) -> Result<Bytes, VaultError> {
+        Self::require_initialized(&env)?;
+        let (next, len) = Self::log_pos(&env, OP_LOG_POS_KEY);
+        let mut page = Bytes::new(&env);
+        let mut i = offset;
+        while i < len && i - offset < limit {
+            let record: Option<Bytes> = env
+                .storage()
+                .persistent()
+                .get(&(Symbol::new(&env, OP_LOG_KEY), next - 1 - i as u64));
+            if let Some(record) = record {
+                page.extend_from_array(&record.len().to_be_bytes());
+                page.append(&record);
+            }
+            i += 1;
+        }
+        Ok(page)
+    }
+
+    /// Return whether events are emitted (true unless the admin disabled them).
+    pub fn events_enabled(env: Env) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(Self::events_on(&env))
+    }
+
+    /// Page through the deduct log, newest first. Holds at most `deduct_log_size()`
+    /// records; older deducts have been evicted and are only in the event stream.
+    /// Fails with `BalancePrivate` on a private-balance vault, since records carry the
+    /// balance after each deduct.
+    pub fn recent_deducts(
+        env: Env,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<DeductRecord>, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        let log = Self::deduct_log(&env);
+        let mut page = Vec::new(&env);
+        let len = log.len();
+        let mut i = offset;
+        while i < len && i - offset < limit {
+            if let Some(record) = log.get(len - 1 - i) {
+                page.push_back(record);
+            }
+            i += 1;
+        }
+        Ok(page)
+    }
+
+    /// Estimate how many seconds the balance lasts at the recent deduct rate: the amount
+    /// in the deduct log divided by the time since its oldest record. Returns `None` if
+    /// the log is empty (no deducts, or the log is off) or no time has passed since the
+    /// oldest logged deduct. Fails with `BalancePrivate` on a private-balance vault.
+    pub fn runway_seconds(env: Env) -> Result<Option<u64>, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_public_balance(&env)?;
+        let log = Self::deduct_log(&env);
+        let Some(oldest) = log.first() else {
+            return Ok(None);
+        };
+        let window = env.ledger().timestamp().saturating_sub(oldest.timestamp);
+        let deducted: i128 = log.iter().map(|record| record.amount).sum();
+        if window == 0 || deducted <= 0 {
+            return Ok(None);
+        }
+        let balance = Self::meta(&env)?.balance.max(0);
+        let runway = balance * window as i128 / deducted;
+        Ok(Some(u64::try_from(runway).unwrap_or(u64::MAX)))
+    }
+
+    /// Count the deducts in the last `secs` seconds, from the deduct log. The count is
+    /// capped by `deduct_log_size()`, since older records have been evicted, and is
+    /// always 0 while the log is off (size 0).
+    pub fn deduct_count_window(env: Env, secs: u64) -> Result<u32, VaultError> {
+        Self::require_initialized(&env)?;
+        let since = env.ledger().timestamp().saturating_sub(secs);
+        let count = Self::deduct_log(&env)
+            .iter()
+            .filter(|record| record.timestamp >= since)
+            .count();
+        Ok(count as u32)
+    }
+
+    /// Set how many deducts the log keeps. Admin only; 0 turns the log off. Fails with
+    /// `InvalidConfig` above 100. Shrinking evicts the oldest records immediately.
+    pub fn set_deduct_log_size(env: Env, caller: Address, size: u32) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if size > MAX_DEDUCT_LOG_SIZE {
+            return Err(VaultError::InvalidConfig);
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, DEDUCT_LOG_SIZE_KEY), &size);
+        Self::trim_log(&env, DEDUCT_LOG_KEY, DEDUCT_LOG_POS_KEY, size);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return how many deducts the log keeps.
+    pub fn deduct_log_size(env: Env) -> Result<u32, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(Self::log_size(&env))
+    }
+
+    /// Reject a deduct whose request id was already charged less than `secs` ago, so a
+    /// recurring charge id bills at most once per period. Admin only; 0 turns it off.
+    pub fn set_request_id_min_interval(
+        env: Env,
+        caller: Address,
+        secs: u64,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, REQUEST_ID_INTERVAL_KEY), &secs);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the minimum seconds between deducts with the same request id (0 = no limit).
+    pub fn get_request_id_min_interval(env: Env) -> Result<u64, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, REQUEST_ID_INTERVAL_KEY))
+            .unwrap_or(0))
+    }
+
+    /// Return up to `limit` config snapshots, newest first. Every config setter appends
+    /// `(ledger timestamp, sha256 of the full config)`; only the last 20 are kept.
+    pub fn config_history(env: Env, limit: u32) -> Result<Vec<(u64, BytesN<32>)>, VaultError> {
+        Self::require_initialized(&env)?;
+        let history: Vec<(u64, BytesN<32>)> = env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, CONFIG_HISTORY_KEY))
+            .unwrap_or_else(|| Vec::new(&env));
+        let mut page = Vec::new(&env);
+        for entry in history.iter().rev().take(limit as usize) {
+            page.push_back(entry);
+        }
+        Ok(page)
+    }
+
+    /// Canonical XDR encoding of the vault's config, in a fixed order: the owner, the
+    /// admin, whether the balance is private, every admin-settable value (with defaults
+    /// applied), and `blocklist_digest()`. Balances, the tab, revenue and logs are state,
+    /// not config, and are left out. `config_history` stores the sha256 of these bytes, so
+    /// off-chain governance can sign over the preimage and check it against a snapshot.
+    pub fn config_bytes(env: Env) -> Result<Bytes, VaultError> {
+        Self::require_initialized(&env)?;
+        let pending_min_deposit: Option<(i128, u64)> = env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, PENDING_MIN_DEPOSIT_KEY));
+        let config: Vec<Val> = Vec::from_array(
+            &env,
+            [
+                Self::meta(&env)?.owner.into_val(&env),
+                Self::get_admin(env.clone())?.into_val(&env),
+                Self::is_balance_private(env.clone())?.into_val(&env),
+                Self::meta(&env)?.min_deposit.into_val(&env),
+                pending_min_deposit.into_val(&env),
+                Self::get_deduct_tolerance(env.clone())?.into_val(&env),
+                Self::get_deduct_base_fee(env.clone())?.into_val(&env),
+                Self::tab_limit(env.clone())?.into_val(&env),
+                Self::events_on(&env).into_val(&env),
+                Self::get_deduct_cosign(env.clone())?.into_val(&env),
+                Self::get_deposit_bonus(env.clone())?.into_val(&env),
+                Self::log_size(&env).into_val(&env),
+                Self::get_deduct_blackout(env.clone())?.into_val(&env),
+                Self::list_reasons(env.clone())?.into_val(&env),
+                Self::get_low_balance_threshold(env.clone())?.into_val(&env),
+                Self::get_request_id_min_interval(env.clone())?.into_val(&env),
+                Self::is_deduct_restricted(env.clone())?.into_val(&env),
+                Self::get_deposit_tiers(env.clone())?.into_val(&env),
+                Self::get_auth_policy(env.clone())?.into_val(&env),
+                Self::get_min_withdraw(env.clone())?.into_val(&env),
+                Self::get_max_deduct_bps(env.clone())?.into_val(&env),
+                Self::get_max_deducts_per_ledger(env.clone())?.into_val(&env),
+                Self::op_log_capacity(&env).into_val(&env),
+                Self::get_monthly_deduct_budget(env.clone())?.into_val(&env),
+                Self::get_max_deduct_deposit_ratio(env.clone())?.into_val(&env),
+                Self::blocklist_digest(env.clone())?.into_val(&env),
+            ],
+        );
+        Ok(config.to_xdr(&env))
+    }
+
+    /// Return the amount charged under `request_id` and not yet refunded (0 if none).
+    pub fn deduct_amount_for(env: Env, request_id: Symbol) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .get(&(Symbol::new(&env, REQUEST_AMOUNT_KEY), request_id))
+            .unwrap_or(0))
+    }
+
+    /// Refund part of an earlier deduct back to the balance. Admin only. Fails with
+    /// `RefundExceedsDeduct` if `amount` is more than `deduct_amount_for(request_id)`, and
+    /// with `RefundExceedsRevenue` if it is more than `retained_revenue()`. Emits a "partial_refund" event keyed by the original request_id.
+    pub fn partial_refund(
+        env: Env,
+        caller: Address,
+        request_id: Symbol,
+        amount: i128,
+    ) -> Result<i128, VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if amount <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        let remaining = Self::deduct_amount_for(env.clone(), request_id.clone())?;
+        if amount > remaining {
+            return Err(VaultError::RefundExceedsDeduct);
+        }
+        if amount > Self::retained_revenue(env.clone())? {
+            return Err(VaultError::RefundExceedsRevenue);
+        }
+        Self::add_deduct_amount(&env, &request_id, -amount);
+        Self::add_revenue(&env, -amount);
+
+        let mut meta = Self::meta(&env)?;
+        meta.balance += amount;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, META_KEY), &meta);
+        Self::publish(
+            &env,
+            (Symbol::new(&env, "partial_refund"), request_id),
+            (amount, meta.balance),
+        );
+        Ok(meta.balance)
+    }
+
+    /// Block `request_id` so any deduct carrying it fails with `RequestIdBlocked`, e.g. for
+    /// known replays or test data. Admin only. Each newly blocked id advances
+    /// `blocklist_digest()` and is recorded as a config change.
+    pub fn blocklist_request_id(
+        env: Env,
+        caller: Address,
+        request_id: Symbol,
+    ) -> Result<(), VaultError> {
+        Self::require_initialized(&env)?;
+        Self::require_admin(&env, &caller)?;
+        if Self::is_request_id_blocked(env.clone(), request_id.clone())? {
+            return Ok(());
+        }
+        env.storage().persistent().set(
+            &(
+                Symbol::new(&env, BLOCKED_REQUEST_ID_KEY),
+                request_id.clone(),
+            ),
+            &true,
+        );
+        let preimage = (Self::blocklist_digest(env.clone())?, request_id);
+        let digest: BytesN<32> = env.crypto().sha256(&preimage.to_xdr(&env)).into();
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, BLOCKLIST_DIGEST_KEY), &digest);
+        Self::record_config_change(&env)?;
+        Ok(())
+    }
+
+    /// Return the hash chain over blocklisted request ids: starting from 32 zero bytes,
+    /// each newly blocked id sets it to the sha256 of the XDR of `(digest, request_id)`.
+    pub fn blocklist_digest(env: Env) -> Result<BytesN<32>, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, BLOCKLIST_DIGEST_KEY))
+            .unwrap_or_else(|| BytesN::from_array(&env, &[0; 32])))
+    }
+
+    /// Return whether `request_id` is on the deduct blocklist.
+    pub fn is_request_id_blocked(env: Env, request_id: Symbol) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .has(&(Symbol::new(&env, BLOCKED_REQUEST_ID_KEY), request_id)))
+    }
+
+    /// Return whether an `accrue` carrying `request_id` has been processed. Accrual ids
+    /// are tracked apart from deduct ids, so they never block a later deduct.
+    pub fn has_accrual_id(env: Env, request_id: Symbol) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .has(&(Symbol::new(&env, ACCRUAL_ID_KEY), request_id)))
+    }
+
+    /// Return whether a deduct carrying `request_id` has been processed.
+    pub fn has_request_id(env: Env, request_id: Symbol) -> Result<bool, VaultError> {
+        Self::require_initialized(&env)?;
+        Ok(env
+            .storage()
+            .persistent()
+            .has(&(Symbol::new(&env, REQUEST_ID_KEY), request_id)))
+    }
+
+    /// Return, in order, whether each of `ids` has been processed by a deduct.
+    pub fn request_ids_status(env: Env, ids: Vec<Symbol>) -> Result<Vec<bool>, VaultError> {
+        Self::require_initialized(&env)?;
+        let mut statuses = Vec::new(&env);
+        for id in ids.iter() {
+            statuses.push_back(Self::has_request_id(env.clone(), id)?);
+        }
+        Ok(statuses)
+    }
+}
+
+impl CalloraVault {
+    /// Fail with `NotInitialized` unless `init` has already run.
+    /// Called first in every entry point so the precondition is explicit.
+    fn require_initialized(env: &Env) -> Result<(), VaultError> {
+        if !env.storage().instance().has(&Symbol::new(env, META_KEY)) {
+            return Err(VaultError::NotInitialized);
+        }
+        Ok(())
+    }
+
+    /// Load vault metadata without the private-balance check, for internal use.
+    fn meta(env: &Env) -> Result<VaultMeta, VaultError> {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, META_KEY))
+            .ok_or(VaultError::NotInitialized)
+    }
+
+    /// Fail with `BalancePrivate` if the vault was initialized with a private balance.
+    fn require_public_balance(env: &Env) -> Result<(), VaultError> {
+        if Self::is_balance_private(env.clone())? {
+            return Err(VaultError::BalancePrivate);
+        }
+        Ok(())
+    }
+
+    /// Load the USDC token address configured at init.
+    fn usdc_address(env: &Env) -> Result<Address, VaultError> {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, USDC_KEY))
+            .ok_or(VaultError::NotInitialized)
+    }
+
+    /// Treat an empty-symbol request id the same as no request id.
+    fn normalize_request_id(env: &Env, request_id: Option<Symbol>) -> Option<Symbol> {
+        request_id.filter(|rid| *rid != Symbol::new(env, ""))
+    }
+
+    /// Fail with `RequestIdBlocked` if `request_id` is on the blocklist.
+    fn require_request_id_allowed(
+        env: &Env,
+        request_id: &Option<Symbol>,
+    ) -> Result<(), VaultError> {
+        match request_id {
+            Some(rid) if Self::is_request_id_blocked(env.clone(), rid.clone())? => {
+                Err(VaultError::RequestIdBlocked)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark `request_id` as processed at the current ledger timestamp.
+    fn record_request_id(env: &Env, request_id: &Symbol) {
+        env.storage().persistent().set(
+            &(Symbol::new(env, REQUEST_ID_KEY), request_id.clone()),
+            &env.ledger().timestamp(),
+        );
+    }
+
+    /// Add `delta` to the amount charged under `request_id`.
+    fn add_deduct_amount(env: &Env, request_id: &Symbol, delta: i128) {
+        let key = (Symbol::new(env, REQUEST_AMOUNT_KEY), request_id.clone());
+        let amount: i128 = env.storage().persistent().get(&key).unwrap_or(0);
+        env.storage().persistent().set(&key, &(amount + delta));
+    }
+
+    /// Fail with `RequestIdTooSoon` if `request_id` was charged less than the configured
+    /// minimum interval ago.
+    fn require_request_id_interval(env: &Env, request_id: &Symbol) -> Result<(), VaultError> {
+        let interval = Self::get_request_id_min_interval(env.clone())?;
+        if interval == 0 {
+            return Ok(());
+        }
+        let last: Option<u64> = env
+            .storage()
+            .persistent()
+            .get(&(Symbol::new(env, REQUEST_ID_KEY), request_id.clone()));
+        match last {
+            Some(last) if env.ledger().timestamp() < last.saturating_add(interval) => {
+                Err(VaultError::RequestIdTooSoon)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Run every check a single deduct must pass, without changing state, and return
+    /// `(base fee, amount charged, shortfall)`. `cosigned` skips the cosign threshold,
+    /// for callers that already verified the cosigner.
+    fn validate_deduct(
+        env: &Env,
+        caller: &Address,
+        amount: i128,
+        request_id: &Option<Symbol>,
+        reason: &Option<Symbol>,
+        deadline: Option<u64>,
+        cosigned: bool,
+    ) -> Result<(i128, i128, i128), VaultError> {
+        if amount <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        if deadline.is_some_and(|deadline| env.ledger().timestamp() > deadline) {
+            return Err(VaultError::DeadlinePassed);
+        }
+        Self::require_no_blackout(env)?;
+        if !cosigned {
+            if let Some((threshold, _)) = Self::get_deduct_cosign(env.clone())? {
+                if amount > threshold {
+                    return Err(VaultError::CosignRequired);
+                }
+            }
+        }
+        Self::require_authorized_deductor(env, caller)?;
+        Self::require_request_id_allowed(env, request_id)?;
+        Self::require_known_reason(env, reason)?;
+        Self::require_policy_approval(env, caller, amount, request_id)?;
+        if let Some(rid) = request_id {
+            Self::require_request_id_interval(env, rid)?;
+        }
+        Self::require_ledger_deducts_within_limit(env, Self::ledger_deducts_used(env) + 1)?;
+        let base = Self::get_deduct_base_fee(env.clone())?;
+        let total = base + amount;
+        let balance = Self::meta(env)?.balance;
+        if Self::max_deduct_for(env, balance)?.is_some_and(|max| total > max) {
+            return Err(VaultError::AboveMaxDeduct);
+        }
+        let (charged, shortfall) = Self::deduct_charge(env, balance, total)?;
+        Self::require_within_monthly_budget(
+            env,
+            Self::monthly_deduct_used(env.clone())? + charged,
+        )?;
+        if let Some((_, window_secs)) = Self::get_max_deduct_deposit_ratio(env.clone())? {
+            let (_, deposited, deducted) = Self::ratio_window(env, window_secs);
+            Self::require_within_deduct_ratio(env, deposited, deducted + charged)?;
+        }
+        Ok((base, charged, shortfall))
+    }
+
+    /// Charge `amount` (within the deduct tolerance), record the request id and emit
+    /// the "deduct" event. Callers have already checked auth.
+    fn apply_deduct(
+        env: &Env,
+        caller: Address,
+        amount: i128,
+        request_id: Option<Symbol>,
+        reason: Option<Symbol>,
+        deadline: Option<u64>,
+        cosigned: bool,
+    ) -> Result<i128, VaultError> {
+        let request_id = Self::normalize_request_id(env, request_id);
+        let (base, charged, shortfall) = Self::validate_deduct(
+            env,
+            &caller,
+            amount,
+            &request_id,
+            &reason,
+            deadline,
+            cosigned,
+        )?;
+        let total = base + amount;
+        let mut meta = Self::meta(env)?;
+        Self::count_ledger_deducts(env, 1)?;
+        Self::spend_monthly_budget(env, charged)?;
+        Self::track_deduct_deposit_ratio(env, 0, charged)?;
+        Self::add_revenue(env, charged);
+        meta.balance -= charged;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, META_KEY), &meta);
+
+        if let Some(rid) = &request_id {
+            Self::record_request_id(env, rid);
+            Self::add_deduct_amount(env, rid, charged);
+        }
+        Self::log_deduct(env, &caller, charged, meta.balance, &request_id);
+
+        let topics = (
+            Symbol::new(env, "deduct"),
+            caller.clone(),
+            request_id.clone(),
+            reason,
+        );
+        if shortfall > 0 {
+            Self::publish(env, topics, (charged, meta.balance, shortfall));
+        } else {
+            Self::publish(env, topics, (total, meta.balance));
+        }
+        Self::publish_base_fee(env, &caller, request_id, base, amount);
+        Self::signal_low_balance(env, meta.balance)?;
+        Ok(meta.balance)
+    }
+
+    /// Emit the "base_fee" event splitting a deduct into its base and variable parts.
+    /// Nothing is emitted while no base fee is set.
+    fn publish_base_fee(
+        env: &Env,
+        caller: &Address,
+        request_id: Option<Symbol>,
+        base: i128,
+        variable: i128,
+    ) {
+        if base > 0 {
+            let topics = (Symbol::new(env, "base_fee"), caller.clone(), request_id);
+            Self::publish(env, topics, (base, variable));
+        }
+    }
+
+    /// Add `amount` (plus any deposit bonus) to the balance and emit the "deposit" and
+    /// "bonus" events. Callers have already checked the minimum deposit.
+    fn credit_deposit(env: &Env, amount: i128) -> Result<i128, VaultError> {
+        Self::track_deduct_deposit_ratio(env, amount, 0)?;
+        let mut meta = Self::meta(env)?;
+        meta.balance += amount;
+
+        Self::publish(env, (Symbol::new(env, "deposit"),), (amount, meta.balance));
+
+        let bonus = Self::deposit_bonus_for(env, amount)?;
+        if bonus > 0 {
+            meta.balance += bonus;
+            let paid = Self::get_bonus_paid(env.clone())? + bonus;
+            env.storage()
+                .instance()
+                .set(&Symbol::new(env, BONUS_PAID_KEY), &paid);
+            Self::publish(env, (Symbol::new(env, "bonus"),), (bonus, meta.balance));
+        }
+
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, META_KEY), &meta);
+        Self::signal_low_balance(env, meta.balance)?;
+        Ok(meta.balance)
+    }
+
+    /// Remove and return pending deposit `id` if it was initiated by `from`.
+    fn take_pending_deposit(
+        env: &Env,
+        from: &Address,
+        id: u32,
+    ) -> Result<PendingDeposit, VaultError> {
+        let key = (Symbol::new(env, PENDING_DEPOSIT_KEY), id);
+        let pending: PendingDeposit = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .ok_or(VaultError::PendingDepositNotFound)?;
+        if pending.from != *from {
+            return Err(VaultError::PendingDepositNotFound);
+        }
+        env.storage().persistent().remove(&key);
+        Self::add_pending_deposit_total(env, -pending.amount);
+        Ok(pending)
+    }
+
+    fn pending_deposit_total(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, PENDING_DEPOSIT_TOTAL_KEY))
+            .unwrap_or(0)
+    }
+
+    fn add_pending_deposit_total(env: &Env, delta: i128) {
+        let total = Self::pending_deposit_total(env) + delta;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, PENDING_DEPOSIT_TOTAL_KEY), &total);
+    }
+
+    /// The current ratio window as `(start, deposited, deducted)`, starting a new one if
+    /// the stored window has ended.
+    fn ratio_window(env: &Env, window_secs: u64) -> (u64, i128, i128) {
+        let now = env.ledger().timestamp();
+        match env
+            .storage()
+            .instance()
+            .get::<_, (u64, i128, i128)>(&Symbol::new(env, RATIO_WINDOW_KEY))
+        {
+            Some((start, deposited, deducted)) if now < start.saturating_add(window_secs) => {
+                (start, deposited, deducted)
+            }
+            _ => (now, 0, 0),
+        }
+    }
+
+    /// Add a deposit (`deposit > 0`) or a deduct (`deduct > 0`) to the ratio window,
+    /// failing with `DeductDepositRatioExceeded` if deducts pass the allowed share.
+    /// Nothing is tracked while the guard is off.
+    fn track_deduct_deposit_ratio(
+        env: &Env,
+        deposit: i128,
+        deduct: i128,
+    ) -> Result<(), VaultError> {
+        let Some((_, window_secs)) = Self::get_max_deduct_deposit_ratio(env.clone())? else {
+            return Ok(());
+        };
+        let (start, deposited, deducted) = Self::ratio_window(env, window_secs);
+        let (deposited, deducted) = (deposited + deposit, deducted + deduct);
+        if deduct > 0 {
+            Self::require_within_deduct_ratio(env, deposited, deducted)?;
+        }
+        env.storage().instance().set(
+            &Symbol::new(env, RATIO_WINDOW_KEY),
+            &(start, deposited, deducted),
+        );
+        Ok(())
+    }
+
+    /// Fail with `DeductDepositRatioExceeded` if `deducted` passes the allowed share of
+    /// `deposited` while the ratio guard is on.
+    fn require_within_deduct_ratio(
+        env: &Env,
+        deposited: i128,
+        deducted: i128,
+    ) -> Result<(), VaultError> {
+        if let Some((bps, _)) = Self::get_max_deduct_deposit_ratio(env.clone())? {
+            if deducted * 10_000 > deposited * bps as i128 {
+                return Err(VaultError::DeductDepositRatioExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lower retained revenue by a payout of `amount`, down to zero at most.
+    fn spend_revenue(env: &Env, amount: i128) -> Result<(), VaultError> {
+        let revenue = Self::retained_revenue(env.clone())?;
+        Self::add_revenue(env, -amount.min(revenue.max(0)));
+        Ok(())
+    }
+
+    fn add_revenue(env: &Env, delta: i128) {
+        let key = Symbol::new(env, REVENUE_KEY);
+        let revenue: i128 = env.storage().instance().get(&key).unwrap_or(0);
+        env.storage().instance().set(&key, &(revenue + delta));
+    }
+
+    /// Add `amount` to this month's deducts, failing with `MonthlyBudgetExceeded` past
+    /// the monthly budget. Nothing is tracked while no budget is set.
+    fn spend_monthly_budget(env: &Env, amount: i128) -> Result<(), VaultError> {
+        if Self::get_monthly_deduct_budget(env.clone())? == 0 {
+            return Ok(());
+        }
+        let used = Self::monthly_deduct_used(env.clone())? + amount;
+        Self::require_within_monthly_budget(env, used)?;
+        let month = Self::month_index(env.ledger().timestamp());
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, MONTHLY_USED_KEY), &(month, used));
+        Ok(())
+    }
+
+    /// Fail with `MonthlyBudgetExceeded` if `used` passes the monthly budget, if one is set.
+    fn require_within_monthly_budget(env: &Env, used: i128) -> Result<(), VaultError> {
+        let budget = Self::get_monthly_deduct_budget(env.clone())?;
+        if budget > 0 && used > budget {
+            return Err(VaultError::MonthlyBudgetExceeded);
+        }
+        Ok(())
+    }
+
+    /// Months since January 1970 (UTC) for a unix timestamp, using the proleptic
+    /// Gregorian calendar (Hinnant's days-to-civil algorithm).
+    fn month_index(timestamp: u64) -> u32 {
+        let z = (timestamp / 86_400) as i64 + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        ((year - 1970) * 12 + month - 1) as u32
+    }
+
+    /// Count `n` more deducts against the current ledger, failing with `TooManyDeducts`
+    /// past the per-ledger limit. The count starts over in each new ledger.
+    fn count_ledger_deducts(env: &Env, n: u32) -> Result<(), VaultError> {
+        if Self::get_max_deducts_per_ledger(env.clone())? == 0 {
+            return Ok(());
+        }
+        let count = Self::ledger_deducts_used(env);
+        Self::require_ledger_deducts_within_limit(env, count.saturating_add(n))?;
+        env.storage().instance().set(
+            &Symbol::new(env, LEDGER_DEDUCTS_KEY),
+            &(env.ledger().sequence(), count + n),
+        );
+        Ok(())
+    }
+
+    /// Fail with `TooManyDeducts` if `count` deducts in one ledger pass the per-ledger limit.
+    fn require_ledger_deducts_within_limit(env: &Env, count: u32) -> Result<(), VaultError> {
+        let limit = Self::get_max_deducts_per_ledger(env.clone())?;
+        if limit > 0 && count > limit {
+            return Err(VaultError::TooManyDeducts);
+        }
+        Ok(())
+    }
+
+    /// Return how many deducts were already counted in the current ledger.
+    fn ledger_deducts_used(env: &Env) -> u32 {
+        match env
+            .storage()
+            .instance()
+            .get::<_, (u32, u32)>(&Symbol::new(env, LEDGER_DEDUCTS_KEY))
+        {
+            Some((seq, count)) if seq == env.ledger().sequence() => count,
+            _ => 0,
+        }
+    }
+
+    /// Fail as `withdraw` would if `amount` cannot be withdrawn from `balance`.
+    fn require_withdrawable(env: &Env, balance: i128, amount: i128) -> Result<(), VaultError> {
+        if amount <= 0 {
+            return Err(VaultError::AmountNotPositive);
+        }
+        if amount < Self::get_min_withdraw(env.clone())? {
+            return Err(VaultError::BelowMinWithdraw);
+        }
+        if balance < amount {
+            return Err(VaultError::InsufficientBalance);
+        }
+        Ok(())
+    }
+
+    /// The per-deduct cap for a given balance, if a percentage cap is set.
+    fn max_deduct_for(env: &Env, balance: i128) -> Result<Option<i128>, VaultError> {
+        let bps = Self::get_max_deduct_bps(env.clone())?;
+        Ok((bps > 0).then(|| balance * bps as i128 / 10_000))
+    }
+
+    /// Fail with `PolicyRejected` if an auth policy is set and does not approve the deduct.
+    fn require_policy_approval(
+        env: &Env,
+        caller: &Address,
+        amount: i128,
+        request_id: &Option<Symbol>,
+    ) -> Result<(), VaultError> {
+        let Some(policy) = Self::get_auth_policy(env.clone())? else {
+            return Ok(());
+        };
+        let args: Vec<Val> = Vec::from_array(
+            env,
+            [
+                caller.into_val(env),
+                amount.into_val(env),
+                request_id.into_val(env),
+            ],
+        );
+        let approved: bool = env.invoke_contract(&policy, &Symbol::new(env, "authorize"), args);
+        if !approved {
+            return Err(VaultError::PolicyRejected);
+        }
+        Ok(())
+    }
+
+    /// Fail with `NotAValidTier` if deposit tiers are set and `amount` is not one of them.
+    fn require_deposit_tier(env: &Env, amount: i128) -> Result<(), VaultError> {
+        let tiers = Self::get_deposit_tiers(env.clone())?;
+        if !tiers.is_empty() && !tiers.contains(amount) {
+            return Err(VaultError::NotAValidTier);
+        }
+        Ok(())
+    }
+
+    /// USDC the vault owes: the owner's credit, pending deposits that may still be
+    /// refunded, and retained revenue held for the operator. Shared by `solvency`,
+    /// `required_reserve`, `withdraw_revenue` and the deposit bonus so they always agree.
+    fn reserve(env: &Env) -> Result<i128, VaultError> {
+        Ok(Self::meta(env)?.balance
+            + Self::pending_deposit_total(env)
+            + Self::retained_revenue(env.clone())?)
+    }
+
+    /// Bonus to credit on a deposit of `amount`: `amount * bps / 10000`, limited to what
+    /// is left under the cap. Returns 0 if the vault's USDC cannot cover it on top of the
+    /// reserve (with `amount` credited), so a bonus never makes the vault insolvent or
+    /// spends the operator's revenue.
+    fn deposit_bonus_for(env: &Env, amount: i128) -> Result<i128, VaultError> {
+        let Some((bps, cap)) = Self::get_deposit_bonus(env.clone())? else {
+            return Ok(0);
+        };
+        let remaining = cap - Self::get_bonus_paid(env.clone())?;
+        let bonus = (amount * bps as i128 / 10_000).min(remaining);
+        if bonus <= 0 {
+            return Ok(0);
+        }
+        let owed = Self::reserve(env)? + amount;
+        let usdc = token::Client::new(env, &Self::usdc_address(env)?);
+        if usdc.balance(&env.current_contract_address()) < owed + bonus {
+            return Ok(0);
+        }
+        Ok(bonus)
+    }
+
+    /// Return the logged deducts, oldest first.
+    fn deduct_log(env: &Env) -> Vec<DeductRecord> {
+        let (next, len) = Self::log_pos(env, DEDUCT_LOG_POS_KEY);
+        let mut log = Vec::new(env);
+        for index in next - len as u64..next {
+            if let Some(record) = env
+                .storage()
+                .persistent()
+                .get(&(Symbol::new(env, DEDUCT_LOG_KEY), index))
+            {
+                log.push_back(record);
+            }
+        }
+        log
+    }
+
+    /// Return a log's `(index of the next record, records held)`. Records live under
+    /// persistent `(log_key, index)` keys, so the instance entry stays small.
+    fn log_pos(env: &Env, pos_key: &str) -> (u64, u32) {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, pos_key))
+            .unwrap_or((0, 0))
+    }
+
+    /// Evict a log's oldest records until at most `size` remain.
+    fn trim_log(env: &Env, log_key: &str, pos_key: &str, size: u32) {
+        let (next, mut len) = Self::log_pos(env, pos_key);
+        if len <= size {
+            return;
+        }
+        while len > size {
+            env.storage()
+                .persistent()
+                .remove(&(Symbol::new(env, log_key), next - len as u64));
+            len -= 1;
+        }
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, pos_key), &(next, len));
+    }
+
+    fn log_size(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, DEDUCT_LOG_SIZE_KEY))
+            .unwrap_or(DEFAULT_DEDUCT_LOG_SIZE)
+    }
+
+    /// Append a deduct to the log, evicting the oldest records beyond the size limit.
+    fn log_deduct(
+        env: &Env,
+        caller: &Address,
+        amount: i128,
+        balance_after: i128,
+        request_id: &Option<Symbol>,
+    ) {
+        let size = Self::log_size(env);
+        if size == 0 {
+            return;
+        }
+        let (next, len) = Self::log_pos(env, DEDUCT_LOG_POS_KEY);
+        env.storage().persistent().set(
+            &(Symbol::new(env, DEDUCT_LOG_KEY), next),
+            &DeductRecord {
+                caller: caller.clone(),
+                amount,
+                balance_after,
+                request_id: request_id.clone(),
+                timestamp: env.ledger().timestamp(),
+            },
+        );
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, DEDUCT_LOG_POS_KEY), &(next + 1, len + 1));
+        Self::trim_log(env, DEDUCT_LOG_KEY, DEDUCT_LOG_POS_KEY, size);
+    }
+
+    /// Fail with `UnknownReason` if `reason` is given, the registry is non-empty, and
+    /// `reason` is not registered.
+    fn require_known_reason(env: &Env, reason: &Option<Symbol>) -> Result<(), VaultError> {
+        if let Some(code) = reason {
+            let codes = Self::list_reasons(env.clone())?;
+            if !codes.is_empty() && !codes.contains(code) {
+                return Err(VaultError::UnknownReason);
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit "low_balance" once when `balance` drops below the threshold; re-arm once the
+    /// balance is back at or above it.
+    fn signal_low_balance(env: &Env, balance: i128) -> Result<(), VaultError> {
+        let threshold = Self::get_low_balance_threshold(env.clone())?;
+        let key = Symbol::new(env, LOW_BALANCE_SIGNALED_KEY);
+        let signaled: bool = env.storage().instance().get(&key).unwrap_or(false);
+        if balance < threshold {
+            if !signaled {
+                env.storage().instance().set(&key, &true);
+                Self::publish(
+                    env,
+                    (Symbol::new(env, "low_balance"),),
+                    (balance, threshold),
+                );
+            }
+        } else if signaled {
+            env.storage().instance().remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Split a requested deduct into `(charged, shortfall)` against `balance`,
+    /// failing if the shortfall exceeds the deduct tolerance.
+    fn deduct_charge(env: &Env, balance: i128, amount: i128) -> Result<(i128, i128), VaultError> {
+        let shortfall = (amount - balance).max(0);
+        if shortfall > Self::get_deduct_tolerance(env.clone())? {
+            return Err(VaultError::InsufficientBalance);
+        }
+        Ok((amount - shortfall, shortfall))
+    }
+
+    /// Transfer `amount` of `usdc` and check that the vault's own token balance moved by
+    /// exactly that much, so a token that reports success without moving funds is caught.
+    /// A transfer from an address to itself must leave the balance unchanged.
+    fn checked_transfer(
+        env: &Env,
+        usdc: &token::Client,
+        from: &Address,
+        to: &Address,
+        amount: i128,
+    ) -> Result<(), VaultError> {
+        let vault = env.current_contract_address();
+        let before = usdc.balance(&vault);
+        usdc.transfer(from, to, &amount);
+        let after = usdc.balance(&vault);
+
+        let expected = if from == to {
+            before
+        } else if *to == vault {
+            before + amount
+        } else if *from == vault {
+            before - amount
+        } else {
+            before
+        };
+        if after != expected {
+            return Err(VaultError::TokenTransferMismatch);
+        }
+        Ok(())
+    }
+
+    /// Hash of `config_bytes`, so two snapshots match exactly when the rules in force did.
+    fn config_hash(env: &Env) -> Result<BytesN<32>, VaultError> {
+        Ok(env
+            .crypto()
+            .sha256(&Self::config_bytes(env.clone())?)
+            .into())
+    }
+
+    /// Append a snapshot of the current config to the bounded config history.
+    fn record_config_change(env: &Env) -> Result<(), VaultError> {
+        let key = Symbol::new(env, CONFIG_HISTORY_KEY);
+        let mut history: Vec<(u64, BytesN<32>)> = env
+            .storage()
+            .instance()
+            .get(&key)
+            .unwrap_or_else(|| Vec::new(env));
+        history.push_back((env.ledger().timestamp(), Self::config_hash(env)?));
+        while history.len() > CONFIG_HISTORY_LEN {
+            history.pop_front();
+        }
+        env.storage().instance().set(&key, &history);
+        Ok(())
+    }
+
+    /// Read the events toggle; absent means enabled.
+    fn events_on(env: &Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, EVENTS_ENABLED_KEY))
+            .unwrap_or(true)
+    }
+
+    fn op_log_capacity(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, OP_LOG_SIZE_KEY))
+            .unwrap_or(0)
+    }
+
+    /// Publish an event unless the admin has disabled event emission. While the op log
+    /// is on, the event is appended to it instead.
+    fn publish<T, D>(env: &Env, topics: T, data: D)
+    where
+        T: Topics,
+        D: IntoVal<Env, Val>,
+    {
+        if !Self::events_on(env) {
+            return;
+        }
+        let size = Self::op_log_capacity(env);
+        if size == 0 {
+            env.events().publish(topics, data);
+            return;
+        }
+        let topics: Vec<Val> = topics.into_val(env);
+        let data: Val = data.into_val(env);
+        let (next, len) = Self::log_pos(env, OP_LOG_POS_KEY);
+        env.storage().persistent().set(
+            &(Symbol::new(env, OP_LOG_KEY), next),
+            &(topics, data).to_xdr(env),
+        );
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, OP_LOG_POS_KEY), &(next + 1, len + 1));
+        Self::trim_log(env, OP_LOG_KEY, OP_LOG_POS_KEY, size);
+    }
+
+    /// Fail with `Unauthorized` if deducts are restricted and `caller` is neither the
+    /// owner nor the admin.
+    fn require_authorized_deductor(env: &Env, caller: &Address) -> Result<(), VaultError> {
+        if Self::is_deduct_restricted(env.clone())?
+            && Self::caller_role(env.clone(), caller.clone())? == CallerRole::None
+        {
+            return Err(VaultError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// Fail with `InBlackout` if the ledger timestamp is inside the deduct blackout window.
+    fn require_no_blackout(env: &Env) -> Result<(), VaultError> {
+        if let Some((start_ts, end_ts)) = Self::get_deduct_blackout(env.clone())? {
+            let now = env.ledger().timestamp();
+            if start_ts <= now && now < end_ts {
+                return Err(VaultError::InBlackout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Require `caller` to sign and to be the current admin.
+    fn require_admin(env: &Env, caller: &Address) -> Result<(), VaultError> {
+        caller.require_auth();
+        if *caller != Self::get_admin(env.clone())? {
+            return Err(VaultError::Unauthorized);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
 
 #[cfg(test)]
 mod test;
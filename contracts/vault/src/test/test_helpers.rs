@@ -0,0 +1,158 @@
+//! Shared setup for vault unit tests.
+
+use crate::{CalloraVault, CalloraVaultClient};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{contract, contractimpl, token, Address, Env, Symbol};
+
+/// Misbehaving token: reports a fixed balance for every holder and accepts
+/// `transfer` without moving anything.
+#[contract]
+pub struct NoopToken;
+
+#[contractimpl]
+impl NoopToken {
+    pub fn balance(_env: Env, _id: Address) -> i128 {
+        1_000
+    }
+
+    pub fn transfer(_env: Env, from: Address, _to: Address, _amount: i128) {
+        from.require_auth();
+    }
+}
+
+/// Auth policy that approves deducts of at most 100 and rejects anything larger.
+#[contract]
+pub struct SmallSpendPolicy;
+
+#[contractimpl]
+impl SmallSpendPolicy {
+    pub fn authorize(
+        _env: Env,
+        _caller: Address,
+        amount: i128,
+        _request_id: Option<Symbol>,
+    ) -> bool {
+        amount <= 100
+    }
+}
+
+pub fn create_usdc<'a>(
+    env: &'a Env,
+    admin: &Address,
+) -> (Address, token::Client<'a>, token::StellarAssetClient<'a>) {
+    let contract_address = env.register_stellar_asset_contract_v2(admin.clone());
+    let address = contract_address.address();
+    let client = token::Client::new(env, &address);
+    let admin_client = token::StellarAssetClient::new(env, &address);
+    (address, client, admin_client)
+}
+
+pub fn create_vault(env: &Env) -> (Address, CalloraVaultClient<'_>) {
+    let address = env.register(CalloraVault, ());
+    let client = CalloraVaultClient::new(env, &address);
+    (address, client)
+}
+
+fn fund_vault(
+    _env: &Env,
+    usdc_admin_client: &token::StellarAssetClient,
+    vault_address: &Address,
+    amount: i128,
+) {
+    usdc_admin_client.mint(vault_address, &amount);
+}
+
+/// An initialized vault and its USDC token, as produced by [`TestVaultBuilder::build`].
+pub struct TestVault<'a> {
+    pub vault_client: CalloraVaultClient<'a>,
+    pub vault_address: Address,
+    pub usdc_client: token::Client<'a>,
+    pub usdc_address: Address,
+    pub owner: Address,
+}
+
+/// Builds an initialized vault in one expression. Mocks all auths on the env;
+/// tests that need real auth checks should reset them after `build()`.
+pub struct TestVaultBuilder<'a> {
+    env: &'a Env,
+    owner: Option<Address>,
+    initial_balance: Option<i128>,
+    min_deposit: Option<i128>,
+    balance_private: Option<bool>,
+    enforce_min_on_init: Option<bool>,
+    usdc_funding: i128,
+}
+
+impl<'a> TestVaultBuilder<'a> {
+    pub fn new(env: &'a Env) -> Self {
+        Self {
+            env,
+            owner: None,
+            initial_balance: None,
+            min_deposit: None,
+            balance_private: None,
+            enforce_min_on_init: None,
+            usdc_funding: 0,
+        }
+    }
+
+    /// Vault owner (and initial admin). A fresh address is generated if unset.
+    pub fn owner(mut self, owner: Address) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn initial_balance(mut self, amount: i128) -> Self {
+        self.initial_balance = Some(amount);
+        self
+    }
+
+    pub fn min_deposit(mut self, amount: i128) -> Self {
+        self.min_deposit = Some(amount);
+        self
+    }
+
+    pub fn balance_private(mut self, private: bool) -> Self {
+        self.balance_private = Some(private);
+        self
+    }
+
+    pub fn enforce_min_on_init(mut self, enforce: bool) -> Self {
+        self.enforce_min_on_init = Some(enforce);
+        self
+    }
+
+    /// USDC minted to the vault contract before `init`.
+    pub fn usdc_funding(mut self, amount: i128) -> Self {
+        self.usdc_funding = amount;
+        self
+    }
+
+    pub fn build(self) -> TestVault<'a> {
+        let env = self.env;
+        env.mock_all_auths();
+
+        let owner = self.owner.unwrap_or_else(|| Address::generate(env));
+        let (vault_address, vault_client) = create_vault(env);
+        let (usdc_address, usdc_client, usdc_admin_client) = create_usdc(env, &owner);
+        if self.usdc_funding > 0 {
+            fund_vault(env, &usdc_admin_client, &vault_address, self.usdc_funding);
+        }
+        vault_client.init(
+            &owner,
+            &usdc_address,
+            &self.initial_balance,
+            &self.min_deposit,
+            &self.balance_private,
+            &self.enforce_min_on_init,
+        );
+
+        TestVault {
+            vault_client,
+            vault_address,
+            usdc_client,
+            usdc_address,
+            owner,
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Helpers for test suites outside this crate. Enable with the `testutils` feature.
+
+use crate::{CalloraVault, VaultError};
+use soroban_sdk::{Address, BytesN, Env, Symbol, Vec};
+
+/// Snapshot of a vault's roles, balances and every admin-settable value, read in one
+/// call. Logs, pending deposits and per-request-id records are not included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestVaultState {
+    pub owner: Address,
+    pub admin: Address,
+    pub balance: i128,
+    pub min_deposit: i128,
+    pub deduct_tolerance: i128,
+    pub balance_private: bool,
+    pub tab_balance: i128,
+    pub tab_limit: i128,
+    pub events_enabled: bool,
+    pub retained_revenue: i128,
+    pub pending_deposit_total: i128,
+    pub bonus_paid: i128,
+    pub monthly_deduct_used: i128,
+    pub deduct_base_fee: i128,
+    pub deduct_cosign: Option<(i128, Address)>,
+    pub deduct_blackout: Option<(u64, u64)>,
+    pub deduct_restricted: bool,
+    pub auth_policy: Option<Address>,
+    pub reasons: Vec<Symbol>,
+    pub max_deduct_bps: u32,
+    pub max_deducts_per_ledger: u32,
+    pub monthly_deduct_budget: i128,
+    pub max_deduct_deposit_ratio: Option<(u32, u64)>,
+    pub request_id_min_interval: u64,
+    pub min_withdraw: i128,
+    pub low_balance_threshold: i128,
+    pub deposit_tiers: Vec<i128>,
+    pub deposit_bonus: Option<(u32, i128)>,
+    pub deduct_log_size: u32,
+    pub op_log_size: u32,
+    pub blocklist_digest: BytesN<32>,
+}
+
+impl CalloraVault {
+    /// Read the state of the vault at `vault_address` without going through
+    /// individual getters (and without the private-balance check).
+    pub fn get_test_state(
+        env: &Env,
+        vault_address: &Address,
+    ) -> Result<TestVaultState, VaultError> {
+        env.as_contract(vault_address, || {
+            let meta = Self::meta(env)?;
+            Ok(TestVaultState {
+                owner: meta.owner,
+                admin: Self::get_admin(env.clone())?,
+                balance: meta.balance,
+                min_deposit: Self::get_effective_min_deposit(env.clone())?,
+                deduct_tolerance: Self::get_deduct_tolerance(env.clone())?,
+                balance_private: Self::is_balance_private(env.clone())?,
+                tab_balance: Self::tab_balance(env.clone())?,
+                tab_limit: Self::tab_limit(env.clone())?,
+                events_enabled: Self::events_enabled(env.clone())?,
+                retained_revenue: Self::retained_revenue(env.clone())?,
+                pending_deposit_total: Self::total_pending_deposits(env.clone())?,
+                bonus_paid: Self::get_bonus_paid(env.clone())?,
+                monthly_deduct_used: Self::monthly_deduct_used(env.clone())?,
+                deduct_base_fee: Self::get_deduct_base_fee(env.clone())?,
+                deduct_cosign: Self::get_deduct_cosign(env.clone())?,
+                deduct_blackout: Self::get_deduct_blackout(env.clone())?,
+                deduct_restricted: Self::is_deduct_restricted(env.clone())?,
+                auth_policy: Self::get_auth_policy(env.clone())?,
+                reasons: Self::list_reasons(env.clone())?,
+                max_deduct_bps: Self::get_max_deduct_bps(env.clone())?,
+                max_deducts_per_ledger: Self::get_max_deducts_per_ledger(env.clone())?,
+                monthly_deduct_budget: Self::get_monthly_deduct_budget(env.clone())?,
+                max_deduct_deposit_ratio: Self::get_max_deduct_deposit_ratio(env.clone())?,
+                request_id_min_interval: Self::get_request_id_min_interval(env.clone())?,
+                min_withdraw: Self::get_min_withdraw(env.clone())?,
+                low_balance_threshold: Self::get_low_balance_threshold(env.clone())?,
+                deposit_tiers: Self::get_deposit_tiers(env.clone())?,
+                deposit_bonus: Self::get_deposit_bonus(env.clone())?,
+                deduct_log_size: Self::deduct_log_size(env.clone())?,
+                op_log_size: Self::op_log_size(env.clone())?,
+                blocklist_digest: Self::blocklist_digest(env.clone())?,
+            })
+        })
+    }
+}